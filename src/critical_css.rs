@@ -0,0 +1,203 @@
+use ego_tree::NodeId;
+use regex::{Captures, Regex};
+use scraper::{Html, Node, Selector};
+use std::collections::{HashMap, HashSet};
+use crate::css_analyzer::{Stylesheet, static_selector};
+use crate::css_parser::{CssRuleKind, parse_stylesheet};
+use crate::html_manager::inline_critical_css_in_html;
+use crate::url_resolver::{is_remote_url, relative_url, resolve_url};
+
+/// Most common desktop screen size
+pub const DEFAULT_VIEWPORT: (u32, u32) = (1366, 768);
+
+/// Rough text metrics used to estimate how far down the page content goes
+const LINE_HEIGHT: f64 = 24.0;
+const CHAR_WIDTH: f64 = 8.0;
+/// Height of media without declared dimensions, the default size of an `<iframe>`
+const DEFAULT_MEDIA_HEIGHT: f64 = 150.0;
+
+/// Estimate which elements are displayed in the first viewport, walking the document in order
+/// and stacking the estimated height of its text and media
+pub fn above_the_fold_elements(document: &Html, viewport: (u32, u32)) -> HashSet<NodeId> {
+    let (viewport_width, viewport_height) = (viewport.0 as f64, viewport.1 as f64);
+
+    let skipped_selector = Selector::parse("head, script, style, noscript, template").unwrap();
+    let skipped: HashSet<NodeId> = document.select(&skipped_selector)
+        .flat_map(|element| element.descendants().map(|node| node.id()))
+        .collect();
+
+    let mut elements: HashSet<NodeId> = HashSet::new();
+    let mut height = 0.0;
+
+    for node in document.root_element().descendants() {
+        if height >= viewport_height {
+            break;
+        }
+        if skipped.contains(&node.id()) {
+            continue;
+        }
+
+        match node.value() {
+            Node::Element(element) => {
+                elements.insert(node.id());
+                let attribute = |name: &str| element.attr(name).and_then(|v| v.trim_end_matches("px").parse::<f64>().ok());
+                height += match element.name() {
+                    "img" | "video" | "iframe" | "canvas" | "svg" | "embed" | "object" => {
+                        match (attribute("width"), attribute("height")) {
+                            // Media wider than the viewport are scaled down
+                            (Some(width), Some(height)) if width > viewport_width => height * viewport_width / width,
+                            (_, Some(height)) => height,
+                            _ => DEFAULT_MEDIA_HEIGHT,
+                        }
+                    },
+                    "input" | "select" | "textarea" => LINE_HEIGHT,
+                    _ => 0.0,
+                };
+            },
+            Node::Text(text) => {
+                let characters = text.trim().chars().count();
+                if characters > 0 {
+                    let characters_per_line = (viewport_width / CHAR_WIDTH).max(1.0);
+                    height += (characters as f64 / characters_per_line).ceil() * LINE_HEIGHT;
+                }
+            },
+            _ => {},
+        }
+    }
+
+    elements
+}
+
+/// Whether a `media` attribute or `@media` prelude only targets print
+fn is_print_only(media: &str) -> bool {
+    let media = media.to_lowercase();
+    media.contains("print") && !media.contains("screen") && !media.contains("all")
+}
+
+pub struct CriticalCss {
+    pub css: String,
+    pub rules: usize,
+    /// Stylesheets linked by the page that stop blocking the rendering
+    pub deferred_urls: Vec<String>,
+    /// Bytes of those stylesheets and of the stylesheets they import
+    pub deferred_bytes: usize,
+    pub above_the_fold_elements: usize,
+}
+
+/// Collect the rules of the render-blocking stylesheets that apply to the first viewport
+pub fn extract_critical_css(document: &Html, base_url: &str, stylesheets: &[Stylesheet], viewport: (u32, u32)) -> CriticalCss {
+    let elements = above_the_fold_elements(document, viewport);
+
+    // Stylesheets limited to print do not block the rendering
+    let link_selector = Selector::parse("link[rel='stylesheet'][href]").unwrap();
+    let deferred_urls: Vec<String> = document.select(&link_selector)
+        .filter(|link| !link.value().attr("media").is_some_and(is_print_only))
+        .filter_map(|link| link.value().attr("href"))
+        .map(|href| resolve_url(base_url, href))
+        .collect();
+
+    let imported_by: HashMap<&str, &str> = stylesheets.iter()
+        .filter_map(|stylesheet| stylesheet.imported_by.as_deref().map(|parent| (stylesheet.url.as_str(), parent)))
+        .collect();
+
+    let url_re = Regex::new(r#"(?i)url\(\s*(["']?)([^"')]+)["']?\s*\)"#).unwrap();
+    let mut css = String::new();
+    let mut rule_count = 0;
+    let mut deferred_bytes = 0;
+
+    for stylesheet in stylesheets {
+        if stylesheet.inline {
+            continue;
+        }
+        // Imported stylesheets are deferred with the linked stylesheet importing them
+        let mut root_url = stylesheet.url.as_str();
+        while let Some(parent) = imported_by.get(root_url) {
+            root_url = parent;
+        }
+        if !deferred_urls.iter().any(|url| url == root_url) {
+            continue;
+        }
+        deferred_bytes += stylesheet.content.len();
+
+        let rules = parse_stylesheet(&stylesheet.content);
+        let mut critical = vec![false; rules.len()];
+        for (index, rule) in rules.iter().enumerate() {
+            if rule.kind != CssRuleKind::Style || rule.at_rules.iter().any(|at_rule| is_print_only(at_rule)) {
+                continue;
+            }
+            critical[index] = rule.selectors.iter()
+                .filter_map(|selector| static_selector(selector))
+                .any(|selector| document.select(&selector).any(|element| elements.contains(&element.id())));
+        }
+
+        // Nested rules are written out with their outermost style rule
+        for index in (0..rules.len()).rev() {
+            if !critical[index] {
+                continue;
+            }
+            let mut ancestor = rules[index].parent;
+            while let Some(parent) = ancestor && rules[parent].kind != CssRuleKind::Style {
+                ancestor = rules[parent].parent;
+            }
+            if let Some(style_parent) = ancestor {
+                critical[style_parent] = true;
+                critical[index] = false;
+            }
+        }
+
+        for (rule, is_critical) in rules.iter().zip(critical) {
+            if !is_critical {
+                continue;
+            }
+            // Once inlined, relative URLs resolve against the page instead of the stylesheet
+            let mut text = url_re.replace_all(stylesheet.content[rule.range.clone()].trim(), |cap: &Captures| {
+                let href = cap[2].trim();
+                if href.starts_with("data:") || href.starts_with('#') || href.starts_with('/') || is_remote_url(href) {
+                    return cap[0].to_string();
+                }
+                format!("url(\"{}\")", relative_url(base_url, &resolve_url(&stylesheet.url, href)))
+            }).to_string();
+            for at_rule in rule.at_rules.iter().rev() {
+                text = format!("{}{{{}}}", at_rule, text);
+            }
+            css.push_str(&text);
+            css.push('\n');
+            rule_count += 1;
+        }
+    }
+
+    CriticalCss {
+        css,
+        rules: rule_count,
+        deferred_urls,
+        deferred_bytes,
+        above_the_fold_elements: elements.len(),
+    }
+}
+
+/// Inline the CSS needed by the first viewport in `<head>` and load the stylesheets asynchronously.
+/// With `write`, the local HTML file is updated, otherwise the savings are only reported.
+pub fn inline_critical_css(html: &str, base_url: &str, stylesheets: &[Stylesheet], viewport: (u32, u32), write: bool) {
+    use colored::*;
+
+    println!("\n{}", format!("⚡ Extracting critical CSS (viewport {}x{})...", viewport.0, viewport.1).cyan().bold());
+
+    let document = Html::parse_document(html);
+    let critical = extract_critical_css(&document, base_url, stylesheets, viewport);
+
+    if critical.deferred_urls.is_empty() {
+        println!("   No render-blocking stylesheet found");
+        return;
+    }
+
+    let removed = critical.deferred_bytes.saturating_sub(critical.css.len());
+    println!("   Above-the-fold elements: {}", critical.above_the_fold_elements);
+    println!("   Render-blocking CSS: {} bytes in {} stylesheets", critical.deferred_bytes, critical.deferred_urls.len());
+    println!("   Critical CSS: {} bytes ({} rules)", critical.css.len(), critical.rules);
+    println!("   Removed from the critical path: {} bytes, {} requests",
+             removed.to_string().green().bold(), critical.deferred_urls.len());
+
+    if write && !is_remote_url(base_url) {
+        inline_critical_css_in_html(base_url, &critical.css, &critical.deferred_urls);
+    }
+}
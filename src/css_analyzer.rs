@@ -1,5 +1,9 @@
 use scraper::{Html, Selector};
 use regex::Regex;
+use std::cmp::Reverse;
+use std::collections::HashSet;
+use crate::css_parser::{CssRule, CssRuleKind, GROUPING_AT_RULES, parse_stylesheet, split_selector_list};
+use crate::resource_extractor::FetchedResources;
 use crate::url_resolver::resolve_url;
 
 
@@ -7,153 +11,607 @@ pub struct CssAnalysis {
     pub total_selectors: usize,
     pub used_selectors: usize,
     pub unused_selectors: Vec<String>,
+    /// Selectors with unknown pseudo-classes or syntax, counted as used
+    pub unevaluated_selectors: Vec<String>,
     pub total_bytes: usize,
-    pub estimated_waste: usize,
+    /// Bytes of the unused rules, and of the unused selectors of partially used rules
+    pub unused_bytes: usize,
+    pub stylesheets: Vec<StylesheetCoverage>,
+    /// Biggest first
+    pub unused_rules: Vec<UnusedRule>,
 }
 
-pub async fn analyze_css(document: &Html, base_url: &str) -> CssAnalysis {
+pub struct StylesheetCoverage {
+    pub name: String,
+    pub total_bytes: usize,
+    pub unused_bytes: usize,
+}
+
+pub struct UnusedRule {
+    pub stylesheet: String,
+    /// Selector list, or at-rule left empty once its unused rules are removed
+    pub prelude: String,
+    pub at_rules: Vec<String>,
+    pub bytes: usize,
+}
+
+/// How much of a rule the page uses
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RuleUsage {
+    Used,
+    /// Indexes of the items of the selector list matching nothing
+    PartiallyUsed(Vec<usize>),
+    Unused,
+}
+
+pub fn analyze_css(document: &Html, stylesheets: &[Stylesheet]) -> CssAnalysis {
     let mut total_selectors = 0;
     let mut used_selectors = 0;
     let mut unused_selectors: Vec<String> = Vec::new();
+    let mut unevaluated_selectors: Vec<String> = Vec::new();
     let mut total_bytes = 0;
+    let mut unused_bytes = 0;
+    let mut coverages: Vec<StylesheetCoverage> = Vec::new();
+    let mut unused_rules: Vec<UnusedRule> = Vec::new();
+    let mut inline_count = 0;
     
-    // Get all CSS links
-    let css_selector = Selector::parse("link[rel='stylesheet']").unwrap();
-    
-    for element in document.select(&css_selector) {
-        if let Some(href) = element.value().attr("href") {
-            let css_url = resolve_url(base_url, href);
-            
-            if let Ok(css_content) = fetch_css(&css_url).await {
-                total_bytes += css_content.len();
-                
-                // Extract and check selectors
-                let selectors = extract_css_selectors(&css_content);
-                
-                for selector_str in selectors {
-                    total_selectors += 1;
-                    
-                    if is_selector_used(document, &selector_str) {
-                        used_selectors += 1;
-                    } else {
-                        unused_selectors.push(selector_str);
-                    }
-                }
-            }
-        }
-    }
-    
-    // Also check inline <style> tags
-    let style_selector = Selector::parse("style").unwrap();
-    for element in document.select(&style_selector) {
-        let css_content = element.inner_html();
-        total_bytes += css_content.len();
-        
-        let selectors = extract_css_selectors(&css_content);
+    for stylesheet in stylesheets {
+        total_bytes += stylesheet.content.len();
+        let name = if stylesheet.inline {
+            inline_count += 1;
+            format!("<style> #{}", inline_count)
+        } else {
+            stylesheet.url.clone()
+        };
         
-        for selector_str in selectors {
+        let rules = parse_stylesheet(&stylesheet.content);
+        let usages = evaluate_rules(&rules, |selector_str, at_rules| {
             total_selectors += 1;
-            
-            if is_selector_used(document, &selector_str) {
-                used_selectors += 1;
-            } else {
-                unused_selectors.push(selector_str);
+            let result = match_selector(document, selector_str);
+            match result {
+                SelectorMatch::Used => used_selectors += 1,
+                SelectorMatch::Unevaluable => {
+                    used_selectors += 1;
+                    unevaluated_selectors.push(selector_str.to_string());
+                },
+                SelectorMatch::Unused if at_rules.is_empty() => unused_selectors.push(selector_str.to_string()),
+                SelectorMatch::Unused => unused_selectors.push(format!("{} (in {})", selector_str, at_rules.join(" "))),
+            }
+            // Selectors that cannot be evaluated are kept on the safe side
+            result != SelectorMatch::Unused
+        });
+        
+        let removable_bytes = unused_rule_bytes(&rules, &usages);
+        let stylesheet_unused: usize = removable_bytes.iter().sum();
+        for ((rule, usage), bytes) in rules.iter().zip(&usages).zip(removable_bytes) {
+            if *usage != RuleUsage::Unused {
+                continue;
             }
+            let prelude = match &rule.kind {
+                CssRuleKind::AtRule(name) => format!("@{} {}", name, rule.prelude).trim_end().to_string(),
+                _ => rule.prelude.clone(),
+            };
+            unused_rules.push(UnusedRule {
+                stylesheet: name.clone(),
+                prelude,
+                at_rules: rule.at_rules.clone(),
+                bytes,
+            });
         }
+        
+        unused_bytes += stylesheet_unused;
+        coverages.push(StylesheetCoverage {
+            name,
+            total_bytes: stylesheet.content.len(),
+            unused_bytes: stylesheet_unused,
+        });
     }
     
-    // Estimate wasted bytes (rough approximation)
-    let waste_ratio = if total_selectors > 0 {
-        unused_selectors.len() as f64 / total_selectors as f64
-    } else {
-        0.0
-    };
-    let estimated_waste = (total_bytes as f64 * waste_ratio) as usize;
+    unused_rules.sort_by_key(|rule| Reverse(rule.bytes));
     
     CssAnalysis {
         total_selectors,
         used_selectors,
         unused_selectors,
+        unevaluated_selectors,
         total_bytes,
-        estimated_waste,
+        unused_bytes,
+        stylesheets: coverages,
+        unused_rules,
     }
 }
 
-fn extract_css_selectors(css: &str) -> Vec<String> {
-    let mut selectors = Vec::new();
-    
-    // Remove comments
-    let comment_re = Regex::new(r"/\*[\s\S]*?\*/").unwrap();
-    let css_clean = comment_re.replace_all(css, "");
-    
-    // Remove @media, @keyframes, @font-face blocks content but keep selectors inside @media
-    let media_re = Regex::new(r"@media[^{]+\{([\s\S]*?)\}\s*\}").unwrap();
-    let mut all_css = css_clean.to_string();
+/// Decide for every rule whether it is used, `is_used(selector, at_rules)` telling whether a
+/// single selector of a style rule matches. A grouping at-rule such as `@media` is unused when
+/// every rule it holds is. Keyframes, font faces and other at-rules are always considered used.
+pub fn evaluate_rules<F>(rules: &[CssRule], mut is_used: F) -> Vec<RuleUsage>
+where
+    F: FnMut(&str, &[String]) -> bool,
+{
+    let mut usages: Vec<RuleUsage> = rules.iter()
+        .map(|rule| {
+            if rule.kind != CssRuleKind::Style || rule.selectors.is_empty() {
+                return RuleUsage::Used;
+            }
+            
+            let used: Vec<bool> = rule.selectors.iter()
+                .map(|selector_str| is_used(selector_str, &rule.at_rules))
+                .collect();
+            
+            // Nested selectors are the product of the parent selectors and of the selector list items
+            let item_count = split_selector_list(&rule.prelude).len().max(1);
+            let unused_items: Vec<usize> = (0..item_count)
+                .filter(|&item| used.iter().skip(item).step_by(item_count).all(|used| !used))
+                .collect();
+            
+            if unused_items.len() == item_count {
+                RuleUsage::Unused
+            } else if unused_items.is_empty() {
+                RuleUsage::Used
+            } else {
+                RuleUsage::PartiallyUsed(unused_items)
+            }
+        })
+        .collect();
     
-    // Extract selectors from @media blocks
-    for cap in media_re.captures_iter(&css_clean) {
-        if let Some(inner) = cap.get(1) {
-            all_css.push_str(inner.as_str());
+    // Nested rules come after their parent, so walking backwards settles children first
+    let mut has_live_child = vec![false; rules.len()];
+    let mut has_child = vec![false; rules.len()];
+    for index in (0..rules.len()).rev() {
+        let rule = &rules[index];
+        if let CssRuleKind::AtRule(name) = &rule.kind && GROUPING_AT_RULES.contains(&name.as_str()) {
+            let is_empty = has_child[index] && !has_live_child[index] && rule.declarations.is_empty();
+            usages[index] = if is_empty { RuleUsage::Unused } else { RuleUsage::Used };
+        }
+        if let Some(parent) = rule.parent {
+            has_child[parent] = true;
+            if usages[index] != RuleUsage::Unused {
+                has_live_child[parent] = true;
+            }
         }
     }
     
-    // Remove @keyframes, @font-face entirely
-    let at_rules_re = Regex::new(r"@(keyframes|font-face|import|charset)[^{]*\{[^}]*\}").unwrap();
-    let css_no_at = at_rules_re.replace_all(&all_css, "");
-    
-    // Extract selectors (everything before {)
-    let selector_re = Regex::new(r"([^{}]+)\{[^}]*\}").unwrap();
+    usages
+}
+
+/// Bytes removable from each rule: the whole rule, nested rules excepted, when it is unused,
+/// or its unused selectors when it is partially used
+pub fn unused_rule_bytes(rules: &[CssRule], usages: &[RuleUsage]) -> Vec<usize> {
+    rules.iter()
+        .zip(usages)
+        .zip(rule_own_bytes(rules))
+        .map(|((rule, usage), own_bytes)| match usage {
+            RuleUsage::Used => 0,
+            RuleUsage::PartiallyUsed(unused_items) => {
+                // Each removed item takes its separating comma with it
+                let items = split_selector_list(&rule.prelude);
+                unused_items.iter().map(|&i| items[i].len() + 1).sum()
+            },
+            RuleUsage::Unused => own_bytes,
+        })
+        .collect()
+}
+
+/// Bytes of each rule, without the rules nested in it
+fn rule_own_bytes(rules: &[CssRule]) -> Vec<usize> {
+    let mut own_bytes: Vec<usize> = rules.iter().map(|rule| rule.range.len()).collect();
+    for rule in rules {
+        if let Some(parent) = rule.parent {
+            own_bytes[parent] = own_bytes[parent].saturating_sub(rule.range.len());
+        }
+    }
+    own_bytes
+}
+
+/// Pseudo-classes depending only on the document structure, matched as they are
+const STRUCTURAL_PSEUDO_CLASSES: [&str; 15] = [
+    "root", "empty", "first-child", "last-child", "only-child", "first-of-type", "last-of-type",
+    "only-of-type", "nth-child", "nth-last-child", "nth-of-type", "nth-last-of-type", "link", "any-link", "scope",
+];
+
+/// Pseudo-classes depending on user interaction or form state, which a static page never matches
+const STATE_PSEUDO_CLASSES: [&str; 34] = [
+    "hover", "active", "focus", "focus-visible", "focus-within", "visited", "target", "target-within",
+    "checked", "indeterminate", "default", "valid", "invalid", "in-range", "out-of-range", "required",
+    "optional", "read-only", "read-write", "placeholder-shown", "autofill", "user-valid", "user-invalid",
+    "enabled", "disabled", "blank", "playing", "paused", "fullscreen", "modal", "popover-open", "open",
+    "closed", "defined",
+];
+
+/// Pseudo-classes taking a selector list, their arguments are normalized too
+const LOGICAL_PSEUDO_CLASSES: [&str; 5] = ["is", "where", "matches", "-webkit-any", "has"];
+
+/// Pseudo-elements that may be written with a single colon
+const LEGACY_PSEUDO_ELEMENTS: [&str; 4] = ["before", "after", "first-line", "first-letter"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SelectorMatch {
+    Used,
+    Unused,
+    /// Unknown pseudo-class or syntax, the selector is assumed to be used
+    Unevaluable,
+}
+
+pub fn match_selector(document: &Html, selector_str: &str) -> SelectorMatch {
+    match static_selector(selector_str) {
+        Some(selector) if document.select(&selector).next().is_some() => SelectorMatch::Used,
+        Some(_) => SelectorMatch::Unused,
+        None => SelectorMatch::Unevaluable,
+    }
+}
+
+/// The selector as matched against a static document, `None` when it cannot be evaluated
+pub fn static_selector(selector_str: &str) -> Option<Selector> {
+    let (normalized, _) = normalize_selector(selector_str).ok()?;
+    Selector::parse(&normalized).ok()
+}
+
+pub fn is_selector_used(document: &Html, selector_str: &str) -> bool {
+    match_selector(document, selector_str) != SelectorMatch::Unused
+}
+
+/// Rewrite a selector into one the static document can be matched against: state pseudo-classes
+/// and pseudo-elements are removed, arguments of `:is()`, `:where()` and `:has()` are normalized and
+/// `:not()` depending on a state is dropped. Returns the selector and whether anything was removed,
+/// or the pseudo-class that cannot be evaluated.
+fn normalize_selector(selector: &str) -> Result<(String, bool), String> {
+    let chars: Vec<char> = selector.chars().collect();
+    let mut output = String::new();
+    let mut removed_any = false;
+    // Whether the current compound selector has nothing yet, a removed pseudo then becomes `*`
+    let mut compound_empty = true;
+    let mut i = 0;
     
-    for cap in selector_re.captures_iter(&css_no_at) {
-        if let Some(selector_match) = cap.get(1) {
-            let selector_str = selector_match.as_str().trim();
-            
-            // Split multiple selectors (separated by comma)
-            for s in selector_str.split(',') {
-                let s = s.trim();
-                if !s.is_empty() && !s.starts_with('@') {
-                    selectors.push(s.to_string());
+    while i < chars.len() {
+        let c = chars[i];
+        match c {
+            '\\' => {
+                output.extend(chars[i..(i + 2).min(chars.len())].iter());
+                compound_empty = false;
+                i += 2;
+            },
+            '[' => {
+                let end = find_closing(&chars, i, '[', ']');
+                output.extend(chars[i..end].iter());
+                compound_empty = false;
+                i = end;
+            },
+            ' ' | '\t' | '\n' | '>' | '+' | '~' => {
+                output.push(c);
+                compound_empty = true;
+                i += 1;
+            },
+            ':' => {
+                let is_element = chars.get(i + 1) == Some(&':');
+                let name_start = if is_element { i + 2 } else { i + 1 };
+                let mut name_end = name_start;
+                while name_end < chars.len() && (chars[name_end].is_alphanumeric() || chars[name_end] == '-' || chars[name_end] == '_') {
+                    name_end += 1;
                 }
-            }
+                let name: String = chars[name_start..name_end].iter().collect::<String>().to_lowercase();
+                
+                let (arguments, end) = if chars.get(name_end) == Some(&'(') {
+                    let end = find_closing(&chars, name_end, '(', ')');
+                    let inner_end = end.saturating_sub(1).max(name_end + 1);
+                    (Some(chars[name_end + 1..inner_end].iter().collect::<String>()), end)
+                } else {
+                    (None, name_end)
+                };
+                i = end;
+                
+                let kept = if is_element || LEGACY_PSEUDO_ELEMENTS.contains(&name.as_str()) {
+                    None
+                } else if LOGICAL_PSEUDO_CLASSES.contains(&name.as_str()) {
+                    // `:not(:is(:hover))` depends on the state as much as `:not(:hover)`
+                    let (items, removed) = normalize_selector_list(arguments.as_deref().unwrap_or(""))?;
+                    removed_any |= removed;
+                    Some(format!(":{}({})", name, items.join(", ")))
+                } else if name == "not" {
+                    // `:not(:hover)` matches depending on the state, so it cannot narrow the match
+                    let (items, depends_on_state) = normalize_selector_list(arguments.as_deref().unwrap_or(""))?;
+                    if depends_on_state { None } else { Some(format!(":not({})", items.join(", "))) }
+                } else if STRUCTURAL_PSEUDO_CLASSES.contains(&name.as_str()) {
+                    Some(chars[name_start - 1..end].iter().collect())
+                } else if STATE_PSEUDO_CLASSES.contains(&name.as_str()) {
+                    None
+                } else {
+                    return Err(format!(":{}", name));
+                };
+                
+                match kept {
+                    Some(pseudo_class) => {
+                        output.push_str(&pseudo_class);
+                        compound_empty = false;
+                    },
+                    None => {
+                        removed_any = true;
+                        let at_boundary = chars.get(i).is_none_or(|next| matches!(next, ' ' | '\t' | '\n' | '>' | '+' | '~'));
+                        if compound_empty && at_boundary {
+                            output.push('*');
+                            compound_empty = false;
+                        }
+                    },
+                }
+            },
+            _ => {
+                output.push(c);
+                compound_empty = false;
+                i += 1;
+            },
         }
     }
     
-    selectors
+    Ok((output.trim().to_string(), removed_any))
 }
 
-fn is_selector_used(document: &Html, selector_str: &str) -> bool {
-    // Clean up the selector for scraper
-    let clean_selector = clean_selector_for_parsing(selector_str);
-    
-    if clean_selector.is_empty() {
-        return true; // Assume used if we can't parse
+/// Normalize every item of a selector list, returning whether anything was removed from any of them
+fn normalize_selector_list(selector_list: &str) -> Result<(Vec<String>, bool), String> {
+    let mut items = Vec::new();
+    let mut removed_any = false;
+    for item in split_selector_list(selector_list) {
+        let (normalized, removed) = normalize_selector(&item)?;
+        removed_any |= removed;
+        items.push(normalized);
     }
+    Ok((items, removed_any))
+}
+
+/// Index after the bracket closing the one at `start`, skipping strings and nested brackets
+fn find_closing(chars: &[char], start: usize, open: char, close: char) -> usize {
+    let mut depth = 0;
+    let mut quote: Option<char> = None;
+    let mut i = start;
     
-    // Try to parse and match the selector
-    match Selector::parse(&clean_selector) {
-        Ok(selector) => document.select(&selector).next().is_some(),
-        Err(_) => true, // Assume used if we can't parse (pseudo-elements, etc.)
+    while i < chars.len() {
+        let c = chars[i];
+        if let Some(q) = quote {
+            if c == '\\' {
+                i += 1;
+            } else if c == q {
+                quote = None;
+            }
+        } else if c == '"' || c == '\'' {
+            quote = Some(c);
+        } else if c == open {
+            depth += 1;
+        } else if c == close {
+            depth -= 1;
+            if depth == 0 {
+                return i + 1;
+            }
+        }
+        i += 1;
     }
+    chars.len()
 }
 
-fn clean_selector_for_parsing(selector: &str) -> String {
-    let mut s = selector.to_string();
-    
-    // Remove pseudo-elements and pseudo-classes that scraper can't handle
-    let pseudo_re = Regex::new(r"::(before|after|first-line|first-letter|selection|placeholder)").unwrap();
-    s = pseudo_re.replace_all(&s, "").to_string();
-    
-    let pseudo_class_re = Regex::new(r":(hover|active|focus|visited|first-child|last-child|nth-child\([^)]+\)|not\([^)]+\)|disabled|enabled|checked|empty|root|target)").unwrap();
-    s = pseudo_class_re.replace_all(&s, "").to_string();
-    
-    // Remove any remaining invalid characters
-    s = s.trim().to_string();
-    
-    s
+async fn fetch_css(url: &str, fetched: &mut FetchedResources) -> Result<String, Box<dyn std::error::Error>> {
+    let content = fetched.load(url).await?;
+    Ok(String::from_utf8_lossy(content).to_string())
+}
+
+/// A stylesheet of the page, linked, inline or pulled in by `@import`
+pub struct Stylesheet {
+    /// URL relative references resolve against: the stylesheet URL, or the page URL for `<style>` blocks
+    pub url: String,
+    pub content: String,
+    /// A `<style>` block of the page
+    pub inline: bool,
+    /// URL of the stylesheet whose `@import` loaded this one
+    pub imported_by: Option<String>,
+}
+
+/// Stylesheets of the page, linked, inline and imported, read from `fetched` when the page
+/// requests were counted and kept there for the next callers
+pub async fn collect_stylesheets(document: &Html, base_url: &str, fetched: &mut FetchedResources) -> Vec<Stylesheet> {
+    let mut stylesheets: Vec<Stylesheet> = Vec::new();
+    let mut seen: HashSet<String> = HashSet::new();
+
+    let css_selector = Selector::parse("link[rel~='stylesheet' i]").unwrap();
+    for element in document.select(&css_selector) {
+        if let Some(href) = element.value().attr("href") {
+            let css_url = resolve_url(base_url, href);
+            if !seen.insert(css_url.clone()) {
+                continue;
+            }
+            if let Ok(content) = fetch_css(&css_url, fetched).await {
+                stylesheets.push(Stylesheet { url: css_url, content, inline: false, imported_by: None });
+            }
+        }
+    }
+
+    let style_selector = Selector::parse("style").unwrap();
+    for element in document.select(&style_selector) {
+        stylesheets.push(Stylesheet {
+            url: base_url.to_string(),
+            content: element.inner_html(),
+            inline: true,
+            imported_by: None,
+        });
+    }
+
+    // Follow `@import` chains, the seen set stops import cycles
+    let mut index = 0;
+    while index < stylesheets.len() {
+        let imports: Vec<String> = extract_css_references(&stylesheets[index].content, &stylesheets[index].url)
+            .into_iter()
+            .filter(|reference| reference.kind == CssReferenceKind::Stylesheet)
+            .map(|reference| reference.url)
+            .collect();
+
+        for import_url in imports {
+            if !seen.insert(import_url.clone()) {
+                continue;
+            }
+            if let Ok(content) = fetch_css(&import_url, fetched).await {
+                let imported_by = Some(stylesheets[index].url.clone());
+                stylesheets.push(Stylesheet { url: import_url, content, inline: false, imported_by });
+            }
+        }
+        index += 1;
+    }
+
+    stylesheets
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CssReferenceKind {
+    Stylesheet,
+    Font,
+    /// Backgrounds, cursors, masks, list markers...
+    Image,
+}
+
+impl CssReferenceKind {
+    pub fn label(&self) -> &'static str {
+        match self {
+            CssReferenceKind::Stylesheet => "CSS",
+            CssReferenceKind::Font => "Font",
+            CssReferenceKind::Image => "Image",
+        }
+    }
+}
+
+/// A resource loaded by a stylesheet
+pub struct CssReference {
+    /// Resolved against the stylesheet URL
+    pub url: String,
+    pub kind: CssReferenceKind,
+}
+
+/// Find the `@import` rules, `@font-face` sources and `url()` values of a stylesheet
+pub fn extract_css_references(css: &str, css_url: &str) -> Vec<CssReference> {
+    let mut references = Vec::new();
+
+    let import_re = Regex::new(r#"(?i)^(?:url\(\s*["']?([^"')]+)["']?\s*\)|["']([^"']+)["'])"#).unwrap();
+    let url_re = Regex::new(r#"(?i)url\(\s*["']?([^"')]+)["']?\s*\)"#).unwrap();
+
+    for rule in parse_stylesheet(css) {
+        match &rule.kind {
+            CssRuleKind::AtRule(name) if name == "import" => {
+                if let Some(cap) = import_re.captures(&rule.prelude) {
+                    let href = cap.get(1).or(cap.get(2)).unwrap().as_str().trim();
+                    references.push(CssReference {
+                        url: resolve_url(css_url, href),
+                        kind: CssReferenceKind::Stylesheet,
+                    });
+                }
+            },
+            // Sources are handled by `extract_font_faces`, only one of them is downloaded
+            CssRuleKind::AtRule(name) if name == "font-face" => {},
+            _ => {
+                for declaration in &rule.declarations {
+                    for cap in url_re.captures_iter(&declaration.value) {
+                        let href = cap[1].trim();
+                        // Inline data and references to SVG elements of the page (`url(#gradient)`) load nothing
+                        if href.starts_with("data:") || href.starts_with('#') {
+                            continue;
+                        }
+                        let kind = match font_format_from_url(href).as_str() {
+                            "woff2" | "woff" | "truetype" | "opentype" | "embedded-opentype" => CssReferenceKind::Font,
+                            _ => CssReferenceKind::Image,
+                        };
+                        references.push(CssReference {
+                            url: resolve_url(css_url, href),
+                            kind,
+                        });
+                    }
+                }
+            },
+        }
+    }
+
+    for font_face in extract_font_faces(css, css_url) {
+        references.push(CssReference {
+            url: font_face.loaded_source().url.clone(),
+            kind: CssReferenceKind::Font,
+        });
+    }
+
+    references
+}
+
+/// An `@font-face` rule
+pub struct FontFace {
+    pub family: String,
+    /// Sources in declaration order, browsers download the first one they support
+    pub sources: Vec<FontSource>,
+}
+
+pub struct FontSource {
+    /// Resolved against the stylesheet URL
+    pub url: String,
+    /// Declared `format()`, or guessed from the file extension
+    pub format: String,
+}
+
+/// Font formats current browsers load, `-variations` variants included.
+/// EOT only worked in Internet Explorer and SVG fonts in old Safari.
+const SUPPORTED_FONT_FORMATS: [&str; 5] = ["woff2", "woff", "truetype", "opentype", "unknown"];
+
+impl FontFace {
+    /// The source browsers download: the first one whose format they support, whatever
+    /// the sources declared after it
+    pub fn loaded_source(&self) -> &FontSource {
+        self.sources.iter()
+            .find(|source| SUPPORTED_FONT_FORMATS.contains(&source.format.split(['-', ' ']).next().unwrap_or("")))
+            .unwrap_or(&self.sources[0])
+    }
+}
+
+pub fn extract_font_faces(css: &str, css_url: &str) -> Vec<FontFace> {
+    let mut font_faces = Vec::new();
+
+    let source_re = Regex::new(r#"(?i)url\(\s*["']?([^"')]+)["']?\s*\)(?:\s*format\(\s*["']?([^"')]+)["']?\s*\))?"#).unwrap();
+
+    for rule in parse_stylesheet(css) {
+        if rule.kind != CssRuleKind::AtRule("font-face".to_string()) {
+            continue;
+        }
+
+        let mut family = String::new();
+        let mut sources = Vec::new();
+        for declaration in &rule.declarations {
+            match declaration.name.as_str() {
+                "font-family" => family = declaration.value.trim_matches(['"', '\'']).to_string(),
+                // A later `src` overrides the previous ones
+                "src" => {
+                    sources.clear();
+                    for source in source_re.captures_iter(&declaration.value) {
+                        let href = source[1].trim();
+                        if href.starts_with("data:") {
+                            continue;
+                        }
+                        let format = source.get(2)
+                            .map(|m| m.as_str().to_lowercase())
+                            .unwrap_or_else(|| font_format_from_url(href));
+                        sources.push(FontSource {
+                            url: resolve_url(css_url, href),
+                            format,
+                        });
+                    }
+                },
+                _ => {},
+            }
+        }
+
+        if !sources.is_empty() {
+            font_faces.push(FontFace { family, sources });
+        }
+    }
+
+    font_faces
 }
 
-async fn fetch_css(url: &str) -> Result<String, Box<dyn std::error::Error>> {
-    let response = reqwest::get(url).await?;
-    let content = response.text().await?;
-    Ok(content)
+fn font_format_from_url(url: &str) -> String {
+    let path = url.split(['?', '#']).next().unwrap_or("").to_lowercase();
+    match path.rsplit('.').next().unwrap_or("") {
+        "woff2" => "woff2",
+        "woff" => "woff",
+        "ttf" => "truetype",
+        "otf" => "opentype",
+        "eot" => "embedded-opentype",
+        "svg" => "svg",
+        _ => "unknown",
+    }.to_string()
 }
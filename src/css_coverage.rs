@@ -0,0 +1,107 @@
+use std::collections::HashMap;
+use crate::css_analyzer::{SelectorMatch, collect_stylesheets, evaluate_rules, match_selector, unused_rule_bytes};
+use crate::css_parser::parse_stylesheet;
+use crate::page_collector::Page;
+use crate::resource_extractor::FetchedResources;
+
+/// Usage of a stylesheet across the pages loading it
+pub struct StylesheetPageCoverage {
+    pub name: String,
+    /// Pages loading the stylesheet
+    pub pages: usize,
+    pub total_selectors: usize,
+    pub used_on_all_pages: usize,
+    /// Selectors used on some pages only, with the number of pages using them
+    pub used_on_some_pages: Vec<(String, usize)>,
+    /// Selectors no page uses
+    pub unused_selectors: Vec<String>,
+    pub total_bytes: usize,
+    /// Bytes of the rules no page uses
+    pub unused_bytes: usize,
+}
+
+pub struct CssCoverage {
+    pub pages: usize,
+    pub stylesheets: Vec<StylesheetPageCoverage>,
+}
+
+/// Compute which selectors of each stylesheet are used on how many of the pages loading it.
+/// A rule is only unused when no page uses it.
+pub async fn analyze_css_coverage(pages: &[Page], fetched: &mut FetchedResources) -> CssCoverage {
+    // (name, content, indexes of the pages loading it)
+    let mut stylesheet_pages: Vec<(String, String, Vec<usize>)> = Vec::new();
+    let mut by_name: HashMap<String, usize> = HashMap::new();
+
+    for (page_index, page) in pages.iter().enumerate() {
+        let mut inline_count = 0;
+        for stylesheet in collect_stylesheets(&page.document, &page.url, fetched).await {
+            // Inline styles belong to their page only
+            let name = if stylesheet.inline {
+                inline_count += 1;
+                format!("<style> #{} of {}", inline_count, page.url)
+            } else {
+                stylesheet.url.clone()
+            };
+
+            match by_name.get(&name) {
+                Some(&index) => {
+                    let loading_pages = &mut stylesheet_pages[index].2;
+                    if !loading_pages.contains(&page_index) {
+                        loading_pages.push(page_index);
+                    }
+                },
+                None => {
+                    by_name.insert(name.clone(), stylesheet_pages.len());
+                    stylesheet_pages.push((name, stylesheet.content, vec![page_index]));
+                },
+            }
+        }
+    }
+
+    let mut stylesheets = Vec::new();
+    for (name, content, page_indexes) in stylesheet_pages {
+        let rules = parse_stylesheet(&content);
+        let mut total_selectors = 0;
+        let mut used_on_all_pages = 0;
+        let mut used_on_some_pages = Vec::new();
+        let mut unused_selectors = Vec::new();
+
+        let usages = evaluate_rules(&rules, |selector_str, _| {
+            total_selectors += 1;
+            // Selectors that cannot be evaluated are kept on the safe side
+            let used_on = page_indexes.iter()
+                .filter(|&&page_index| match_selector(&pages[page_index].document, selector_str) != SelectorMatch::Unused)
+                .count();
+
+            if used_on == page_indexes.len() {
+                used_on_all_pages += 1;
+            } else if used_on > 0 {
+                used_on_some_pages.push((selector_str.to_string(), used_on));
+            } else {
+                unused_selectors.push(selector_str.to_string());
+            }
+            used_on > 0
+        });
+
+        let unused_bytes = unused_rule_bytes(&rules, &usages).iter().sum();
+
+        // Rarely used selectors first, they are the first candidates for a page-specific stylesheet
+        used_on_some_pages.sort_by_key(|(_, used_on)| *used_on);
+
+        stylesheets.push(StylesheetPageCoverage {
+            name,
+            pages: page_indexes.len(),
+            total_selectors,
+            used_on_all_pages,
+            used_on_some_pages,
+            unused_selectors,
+            total_bytes: content.len(),
+            unused_bytes,
+        });
+    }
+
+    CssCoverage {
+        pages: pages.len(),
+        stylesheets,
+    }
+}
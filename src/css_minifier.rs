@@ -0,0 +1,125 @@
+use lightningcss::stylesheet::{MinifyOptions, ParserOptions, PrinterOptions, StyleSheet};
+use regex::Regex;
+use scraper::Html;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use crate::css_analyzer::collect_stylesheets;
+use crate::resource_extractor::FetchedResources;
+use crate::css_parser::{CssRuleKind, parse_stylesheet};
+use crate::output::print_savings;
+use crate::url_resolver::is_remote_url;
+
+/// Minify a stylesheet: whitespace and comments, shorthands, colors, duplicate declarations
+/// and duplicate rules
+pub fn minify_css(css: &str) -> Result<String, String> {
+    let deduplicated = remove_duplicate_rules(css);
+
+    let mut stylesheet = StyleSheet::parse(&deduplicated, ParserOptions::default())
+        .map_err(|e| format!("failed to parse CSS: {}", e))?;
+    stylesheet.minify(MinifyOptions::default())
+        .map_err(|e| format!("failed to minify CSS: {}", e))?;
+    let output = stylesheet.to_css(PrinterOptions { minify: true, ..PrinterOptions::default() })
+        .map_err(|e| format!("failed to print CSS: {}", e))?;
+
+    Ok(output.code)
+}
+
+/// Grouping at-rules, selectors and declarations of a style rule
+type RuleKey = (Vec<String>, String, Vec<(String, String)>);
+
+/// Remove the style rules repeated identically later in the same context, at the top level or
+/// under the same grouping at-rules. The last copy is the one that wins the cascade, so the
+/// earlier ones have no effect.
+fn remove_duplicate_rules(css: &str) -> String {
+    let rules = parse_stylesheet(css);
+    let has_children: HashSet<usize> = rules.iter().filter_map(|rule| rule.parent).collect();
+
+    // Rule key -> index of its last occurrence
+    let mut last_occurrence: HashMap<RuleKey, usize> = HashMap::new();
+    let mut keys = Vec::new();
+    for (index, rule) in rules.iter().enumerate() {
+        // Nested style rules depend on their parent selector, which is not part of the key
+        let mut ancestor = rule.parent;
+        let mut nested_in_style_rule = false;
+        while let Some(parent) = ancestor {
+            nested_in_style_rule |= rules[parent].kind == CssRuleKind::Style;
+            ancestor = rules[parent].parent;
+        }
+        if rule.kind != CssRuleKind::Style || nested_in_style_rule || has_children.contains(&index) {
+            keys.push(None);
+            continue;
+        }
+        let declarations = rule.declarations.iter()
+            .map(|declaration| (declaration.name.clone(), declaration.value.clone()))
+            .collect();
+        let key = (rule.at_rules.clone(), rule.prelude.clone(), declarations);
+        last_occurrence.insert(key.clone(), index);
+        keys.push(Some(key));
+    }
+
+    let mut output = String::with_capacity(css.len());
+    let mut copied_up_to = 0;
+    for (index, key) in keys.into_iter().enumerate() {
+        let Some(key) = key else { continue };
+        if last_occurrence[&key] != index {
+            let range = &rules[index].range;
+            output.push_str(&css[copied_up_to..range.start]);
+            copied_up_to = range.end;
+        }
+    }
+    output.push_str(&css[copied_up_to..]);
+    output
+}
+
+/// Minify the local stylesheets used by the page in place, and its `<style>` blocks
+pub async fn minify_stylesheets(html: &str, file_path: &str, fetched: &mut FetchedResources) {
+    let document = Html::parse_document(html);
+
+    for stylesheet in collect_stylesheets(&document, file_path, fetched).await {
+        if stylesheet.inline {
+            continue;
+        }
+        if is_remote_url(&stylesheet.url) {
+            println!("⏭️  Skipping remote stylesheet: {}", stylesheet.url);
+            continue;
+        }
+
+        match minify_css(&stylesheet.content) {
+            Ok(minified) if minified.len() < stylesheet.content.len() => {
+                fs::write(&stylesheet.url, &minified)
+                    .unwrap_or_else(|_| panic!("Failed to write to file: {}", stylesheet.url));
+                print_savings(&format!("Minified file saved as {}", stylesheet.url), stylesheet.content.len(), minified.len());
+            },
+            Ok(_) => {},
+            Err(e) => println!("❌ Failed to minify {}: {}", stylesheet.url, e),
+        }
+    }
+
+    // `<style>` blocks are replaced by their byte range in the source, the parsed text may differ
+    // from it (line endings, entities) and the same CSS may appear elsewhere in the page
+    let style_re = Regex::new(r"(?is)<style\b[^>]*>(.*?)</style\s*>").unwrap();
+    let mut modified_html = String::new();
+    let mut copied_up_to = 0;
+    let mut inline_updated = 0;
+
+    for cap in style_re.captures_iter(html) {
+        let content = cap.get(1).unwrap();
+        match minify_css(content.as_str()) {
+            Ok(minified) if minified.len() < content.len() => {
+                inline_updated += 1;
+                print_savings(&format!("Minified <style> #{} in {}", inline_updated, file_path), content.len(), minified.len());
+                modified_html.push_str(&html[copied_up_to..content.start()]);
+                modified_html.push_str(&minified);
+                copied_up_to = content.end();
+            },
+            Ok(_) => {},
+            Err(e) => println!("❌ Failed to minify a <style> block of {}: {}", file_path, e),
+        }
+    }
+
+    if inline_updated > 0 {
+        modified_html.push_str(&html[copied_up_to..]);
+        fs::write(file_path, &modified_html)
+            .unwrap_or_else(|_| panic!("Failed to write to file: {}", file_path));
+    }
+}
@@ -0,0 +1,442 @@
+//! Rule-level CSS parser used by the analyzer, the purger and the critical CSS extraction.
+//!
+//! lightningcss, used for minification, parses into a typed tree and serializes it back: rules
+//! it does not understand are dropped and it gives no byte range of a rule in the source. The
+//! analyzer needs both, to measure and cut rules out of the stylesheet as written, so rules are
+//! split here following the tokenization rules of CSS Syntax Level 3 for strings, escapes,
+//! comments and blocks, without interpreting selectors or values.
+
+use std::ops::Range;
+
+/// At-rules whose block holds rules, the other ones hold declarations or keyframes
+pub const GROUPING_AT_RULES: [&str; 7] = ["media", "supports", "layer", "container", "document", "scope", "starting-style"];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CssRuleKind {
+    Style,
+    /// A keyframe (`from`, `50%`) of a `@keyframes` rule
+    Keyframe,
+    /// Lower-cased at-rule name without the `@`, e.g. `media` or `font-face`
+    AtRule(String),
+}
+
+#[derive(Debug, Clone)]
+pub struct CssDeclaration {
+    /// Lower-cased, except for custom properties
+    pub name: String,
+    /// Value as written, `!important` included
+    pub value: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct CssRule {
+    pub kind: CssRuleKind,
+    /// Selector list of a style rule or prelude of an at-rule, comments removed
+    pub prelude: String,
+    /// Selectors of a style rule, resolved against the parent rule for nested CSS
+    pub selectors: Vec<String>,
+    pub declarations: Vec<CssDeclaration>,
+    /// Bytes of the whole rule in the stylesheet, nested rules included
+    pub range: Range<usize>,
+    /// Offset of the `{` opening the block, or end of the prelude of a statement at-rule
+    pub prelude_end: usize,
+    /// Enclosing at-rules, outermost first, e.g. `@supports (display: grid)` then `@media (min-width: 600px)`
+    pub at_rules: Vec<String>,
+    /// Index of the enclosing rule, at-rules included
+    pub parent: Option<usize>,
+}
+
+/// What the enclosing blocks tell about the content of a block
+#[derive(Clone)]
+struct BlockContext {
+    parent: Option<usize>,
+    at_rules: Vec<String>,
+    /// Selectors nested style rules are relative to
+    selectors: Option<Vec<String>>,
+    in_keyframes: bool,
+    top_level: bool,
+}
+
+struct Parser<'a> {
+    css: &'a str,
+    bytes: &'a [u8],
+    pos: usize,
+    rules: Vec<CssRule>,
+}
+
+/// Parse a stylesheet into its rules, in document order with parents before their nested rules.
+/// Invalid content is skipped the way browsers do, up to the next `;` or block.
+pub fn parse_stylesheet(css: &str) -> Vec<CssRule> {
+    let mut parser = Parser {
+        css,
+        bytes: css.as_bytes(),
+        pos: 0,
+        rules: Vec::new(),
+    };
+    parser.parse_block_content(&BlockContext {
+        parent: None,
+        at_rules: Vec::new(),
+        selectors: None,
+        in_keyframes: false,
+        top_level: true,
+    });
+    parser.rules
+}
+
+impl<'a> Parser<'a> {
+    /// Parse declarations and rules up to the `}` closing the current block
+    fn parse_block_content(&mut self, context: &BlockContext) -> Vec<CssDeclaration> {
+        let mut declarations = Vec::new();
+
+        loop {
+            self.skip_whitespace_and_comments();
+            let Some(&byte) = self.bytes.get(self.pos) else { break };
+
+            match byte {
+                b'}' => {
+                    self.pos += 1;
+                    if context.top_level {
+                        continue;
+                    }
+                    break;
+                },
+                b';' => {
+                    self.pos += 1;
+                    continue;
+                },
+                b'@' => {
+                    self.parse_at_rule(context);
+                    continue;
+                },
+                _ => {},
+            }
+
+            let start = self.pos;
+            // Custom property values may hold `{}` blocks, selectors never start with `--`
+            let is_custom_property = self.css[start..].starts_with("--");
+            let (end, stop) = self.scan_until(start, is_custom_property);
+
+            if stop == Some(b'{') {
+                self.pos = end + 1;
+                let kind = if context.in_keyframes { CssRuleKind::Keyframe } else { CssRuleKind::Style };
+                self.parse_rule_block(kind, start, start, end, context);
+                continue;
+            }
+
+            self.pos = if stop == Some(b';') { end + 1 } else { end };
+            if !context.top_level && let Some(declaration) = parse_declaration(&self.css[start..end]) {
+                declarations.push(declaration);
+            }
+        }
+
+        declarations
+    }
+
+    fn parse_at_rule(&mut self, context: &BlockContext) {
+        let start = self.pos;
+        let name_end = self.css[start + 1..]
+            .find(|c: char| !(c.is_alphanumeric() || c == '-' || c == '_'))
+            .map(|offset| start + 1 + offset)
+            .unwrap_or(self.css.len());
+        let name = self.css[start + 1..name_end].to_lowercase();
+
+        let (end, stop) = self.scan_until(name_end, false);
+
+        if stop == Some(b'{') {
+            self.pos = end + 1;
+            self.parse_rule_block(CssRuleKind::AtRule(name), start, name_end, end, context);
+            return;
+        }
+
+        // Statement at-rules such as `@import` or `@layer base, components;`
+        self.pos = if stop == Some(b';') { end + 1 } else { end };
+        self.rules.push(CssRule {
+            kind: CssRuleKind::AtRule(name),
+            prelude: clean_prelude(&self.css[name_end..end]),
+            selectors: Vec::new(),
+            declarations: Vec::new(),
+            range: start..self.pos,
+            prelude_end: end,
+            at_rules: context.at_rules.clone(),
+            parent: context.parent,
+        });
+    }
+
+    /// Parse the block of a rule starting at `start`, `self.pos` being after the `{`.
+    /// Its prelude is `prelude_start..prelude_end`, after the name for an at-rule.
+    fn parse_rule_block(&mut self, kind: CssRuleKind, start: usize, prelude_start: usize, prelude_end: usize, context: &BlockContext) {
+        let index = self.rules.len();
+        let mut child_context = context.clone();
+        child_context.parent = Some(index);
+        child_context.top_level = false;
+
+        let (prelude, selectors) = match &kind {
+            CssRuleKind::AtRule(name) => {
+                let prelude = clean_prelude(&self.css[prelude_start..prelude_end]);
+                child_context.in_keyframes = name.ends_with("keyframes");
+                child_context.at_rules.push(clean_prelude(&self.css[start..prelude_end]));
+                (prelude, Vec::new())
+            },
+            CssRuleKind::Style => {
+                let prelude = clean_prelude(&self.css[prelude_start..prelude_end]);
+                let selectors = resolve_nested_selectors(context.selectors.as_deref(), &prelude);
+                child_context.selectors = Some(selectors.clone());
+                (prelude, selectors)
+            },
+            CssRuleKind::Keyframe => (clean_prelude(&self.css[prelude_start..prelude_end]), Vec::new()),
+        };
+
+        self.rules.push(CssRule {
+            kind,
+            prelude,
+            selectors,
+            declarations: Vec::new(),
+            range: start..start,
+            prelude_end,
+            at_rules: context.at_rules.clone(),
+            parent: context.parent,
+        });
+
+        let declarations = self.parse_block_content(&child_context);
+        self.rules[index].declarations = declarations;
+        self.rules[index].range = start..self.pos;
+    }
+
+    fn skip_whitespace_and_comments(&mut self) {
+        loop {
+            while self.bytes.get(self.pos).is_some_and(|b| b.is_ascii_whitespace()) {
+                self.pos += 1;
+            }
+            if self.css[self.pos..].starts_with("/*") {
+                self.pos = skip_comment(self.css, self.pos);
+            } else {
+                break;
+            }
+        }
+    }
+
+    /// Find the next `;`, `{` or `}` outside of strings, comments, parentheses and brackets.
+    /// With `nest_braces`, `{}` blocks are skipped too and only `;` or an unmatched `}` stop the scan.
+    fn scan_until(&self, from: usize, nest_braces: bool) -> (usize, Option<u8>) {
+        let mut pos = from;
+        let mut depth = 0;
+
+        while let Some(&byte) = self.bytes.get(pos) {
+            match byte {
+                b'\\' => {
+                    pos += 2;
+                    continue;
+                },
+                b'"' | b'\'' => {
+                    pos = skip_string(self.bytes, pos);
+                    continue;
+                },
+                b'/' if self.bytes.get(pos + 1) == Some(&b'*') => {
+                    pos = skip_comment(self.css, pos);
+                    continue;
+                },
+                b'(' | b'[' => depth += 1,
+                b')' | b']' if depth > 0 => depth -= 1,
+                b'{' if nest_braces => depth += 1,
+                b'}' if nest_braces && depth > 0 => depth -= 1,
+                b';' | b'{' | b'}' if depth == 0 => return (pos, Some(byte)),
+                _ => {},
+            }
+            pos += 1;
+        }
+
+        (self.bytes.len(), None)
+    }
+}
+
+/// Position after the string starting at `start`. Unterminated strings end at the line break.
+fn skip_string(bytes: &[u8], start: usize) -> usize {
+    let quote = bytes[start];
+    let mut pos = start + 1;
+    while let Some(&byte) = bytes.get(pos) {
+        match byte {
+            b'\\' => pos += 2,
+            b'\n' => return pos,
+            _ if byte == quote => return pos + 1,
+            _ => pos += 1,
+        }
+    }
+    bytes.len()
+}
+
+/// Position after the comment starting at `start`
+fn skip_comment(css: &str, start: usize) -> usize {
+    css[start + 2..].find("*/")
+        .map(|offset| start + 2 + offset + 2)
+        .unwrap_or(css.len())
+}
+
+/// Remove comments, outside of strings
+pub fn strip_comments(css: &str) -> String {
+    let bytes = css.as_bytes();
+    let mut output = String::with_capacity(css.len());
+    let mut copied_up_to = 0;
+    let mut pos = 0;
+
+    while let Some(&byte) = bytes.get(pos) {
+        match byte {
+            b'\\' => pos += 2,
+            b'"' | b'\'' => pos = skip_string(bytes, pos),
+            b'/' if bytes.get(pos + 1) == Some(&b'*') => {
+                output.push_str(&css[copied_up_to..pos]);
+                pos = skip_comment(css, pos);
+                copied_up_to = pos;
+            },
+            _ => pos += 1,
+        }
+    }
+
+    output.push_str(&css[copied_up_to.min(css.len())..]);
+    output
+}
+
+/// Comments removed and whitespace collapsed
+fn clean_prelude(prelude: &str) -> String {
+    strip_comments(prelude).split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Parse the declarations of a `style` attribute
+pub fn parse_style_attribute(style: &str) -> Vec<CssDeclaration> {
+    let mut parser = Parser {
+        css: style,
+        bytes: style.as_bytes(),
+        pos: 0,
+        rules: Vec::new(),
+    };
+    parser.parse_block_content(&BlockContext {
+        parent: None,
+        at_rules: Vec::new(),
+        selectors: None,
+        in_keyframes: false,
+        top_level: false,
+    })
+}
+
+fn parse_declaration(declaration: &str) -> Option<CssDeclaration> {
+    let text = strip_comments(declaration);
+    let (name, value) = text.split_once(':')?;
+    let name = name.trim();
+    if name.is_empty() {
+        return None;
+    }
+
+    let name = if name.starts_with("--") { name.to_string() } else { name.to_lowercase() };
+    Some(CssDeclaration {
+        name,
+        value: value.trim().to_string(),
+    })
+}
+
+/// Split a selector list on its top-level commas
+pub fn split_selector_list(selector_list: &str) -> Vec<String> {
+    let bytes = selector_list.as_bytes();
+    let mut selectors = Vec::new();
+    let mut depth = 0;
+    let mut start = 0;
+    let mut pos = 0;
+
+    while let Some(&byte) = bytes.get(pos) {
+        match byte {
+            b'\\' => {
+                pos += 2;
+                continue;
+            },
+            b'"' | b'\'' => {
+                pos = skip_string(bytes, pos);
+                continue;
+            },
+            b'(' | b'[' => depth += 1,
+            b')' | b']' if depth > 0 => depth -= 1,
+            b',' if depth == 0 => {
+                selectors.push(selector_list[start..pos].trim().to_string());
+                start = pos + 1;
+            },
+            _ => {},
+        }
+        pos += 1;
+    }
+    selectors.push(selector_list[start.min(selector_list.len())..].trim().to_string());
+
+    selectors.retain(|selector| !selector.is_empty());
+    selectors
+}
+
+/// Expand the selectors of a nested style rule into full selectors, `&` standing for the parent
+fn resolve_nested_selectors(parents: Option<&[String]>, prelude: &str) -> Vec<String> {
+    let selectors = split_selector_list(prelude);
+    let Some(parents) = parents else { return selectors };
+
+    let mut resolved = Vec::new();
+    for parent in parents {
+        // A complex parent must stay a single unit once substituted
+        let parent_unit = if parent.contains([' ', '>', '+', '~']) {
+            format!(":is({})", parent)
+        } else {
+            parent.clone()
+        };
+
+        for selector in &selectors {
+            if selector.contains('&') {
+                resolved.push(selector.replace('&', &parent_unit));
+            } else {
+                resolved.push(format!("{} {}", parent, selector));
+            }
+        }
+    }
+    resolved
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escapes_strings_and_comments_do_not_end_a_selector() {
+        let css = r#".a\{b { color: red } a[title="}{;"] { color: blue } .c /* { */ .d { margin: 0 }"#;
+        let rules = parse_stylesheet(css);
+        let preludes: Vec<&str> = rules.iter().map(|rule| rule.prelude.as_str()).collect();
+        assert_eq!(preludes, vec![r".a\{b", r#"a[title="}{;"]"#, ".c .d"]);
+        assert_eq!(&css[rules[1].range.clone()], r#"a[title="}{;"] { color: blue }"#);
+        assert_eq!(rules[2].declarations[0].name, "margin");
+    }
+
+    #[test]
+    fn strings_and_comments_do_not_end_an_at_rule_prelude() {
+        let css = r#"@import url("a;b.css") screen; @media /* } */ screen { .e { top: 0 } } @supports (content: "{") { .f { left: 0 } }"#;
+        let rules = parse_stylesheet(css);
+
+        assert_eq!(rules[0].kind, CssRuleKind::AtRule("import".to_string()));
+        assert_eq!(rules[0].prelude, r#"url("a;b.css") screen"#);
+        assert_eq!(rules[1].prelude, "screen");
+        assert_eq!(rules[2].selectors, vec![".e"]);
+        assert_eq!(rules[2].at_rules, vec!["@media screen"]);
+        assert_eq!(rules[3].prelude, r#"(content: "{")"#);
+        assert_eq!(rules[4].selectors, vec![".f"]);
+    }
+
+    #[test]
+    fn custom_properties_may_hold_blocks() {
+        let rules = parse_stylesheet(".g { --mixin: { color: red; }; color: blue } .h { top: 0 }");
+        assert_eq!(rules.len(), 2);
+        assert_eq!(rules[0].declarations[0].name, "--mixin");
+        assert_eq!(rules[0].declarations[1].value, "blue");
+    }
+
+    #[test]
+    fn nested_rules_resolve_against_their_parent() {
+        let rules = parse_stylesheet(".card, .tile { & > .title { x: y } .icon { x: y } }");
+        assert_eq!(rules[1].selectors, vec![".card > .title", ".tile > .title"]);
+        assert_eq!(rules[2].selectors, vec![".card .icon", ".tile .icon"]);
+    }
+
+    #[test]
+    fn at_rule_names_changing_length_when_lower_cased_keep_their_prelude() {
+        let rules = parse_stylesheet("@\u{130}x (a) { .i { top: 0 } }");
+        assert_eq!(rules[0].prelude, "(a)");
+    }
+}
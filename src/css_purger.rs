@@ -0,0 +1,262 @@
+use regex::Regex;
+use scraper::Selector;
+use std::collections::HashSet;
+use std::fs;
+use std::path::Path;
+use crate::css_analyzer::{RuleUsage, Stylesheet, evaluate_rules, is_selector_used};
+use crate::css_parser::{CssRule, CssRuleKind, GROUPING_AT_RULES, parse_style_attribute, parse_stylesheet, split_selector_list};
+use crate::html_manager::change_html_stylesheet_urls;
+use crate::page_collector::Page;
+use crate::url_resolver::is_remote_url;
+
+/// Selectors kept even when the page does not use them, typically classes added by JavaScript
+pub struct Safelist {
+    patterns: Vec<Regex>,
+}
+
+impl Safelist {
+    /// `/pattern/` entries are regexes, other entries match as whole class, id or element names
+    pub fn new(entries: &[String]) -> Result<Safelist, String> {
+        let mut patterns = Vec::new();
+        for entry in entries {
+            let pattern = match entry.strip_prefix('/').and_then(|e| e.strip_suffix('/')) {
+                Some(regex) if !regex.is_empty() => regex.to_string(),
+                _ => format!(r"(^|[^\w-]){}($|[^\w-])", regex::escape(entry)),
+            };
+            let regex = Regex::new(&pattern)
+                .map_err(|e| format!("invalid safelist pattern {}: {}", entry, e))?;
+            patterns.push(regex);
+        }
+        Ok(Safelist { patterns })
+    }
+
+    pub fn matches(&self, selector: &str) -> bool {
+        self.patterns.iter().any(|pattern| pattern.is_match(selector))
+    }
+}
+
+pub struct PurgedStylesheet {
+    pub css: String,
+    pub removed_rules: usize,
+}
+
+/// A stylesheet with the rules the page does not use marked as removed
+pub struct EvaluatedStylesheet {
+    rules: Vec<CssRule>,
+    usages: Vec<RuleUsage>,
+    removed: Vec<bool>,
+}
+
+/// A rule is kept when any of `pages` uses it
+pub fn evaluate_stylesheet(css: &str, pages: &[Page], safelist: &Safelist) -> EvaluatedStylesheet {
+    let rules = parse_stylesheet(css);
+    let usages = evaluate_rules(&rules, |selector, _| {
+        safelist.matches(selector) || pages.iter().any(|page| is_selector_used(&page.document, selector))
+    });
+
+    let mut removed: Vec<bool> = usages.iter().map(|usage| *usage == RuleUsage::Unused).collect();
+    // A rule is gone with its ancestors, rules come after their parent
+    for index in 0..rules.len() {
+        if let Some(parent) = rules[index].parent && removed[parent] {
+            removed[index] = true;
+        }
+    }
+
+    EvaluatedStylesheet { rules, usages, removed }
+}
+
+impl EvaluatedStylesheet {
+    /// Values of the declarations left that may name an animation or a font
+    pub fn reference_values(&self) -> Vec<String> {
+        self.rules.iter()
+            .zip(&self.removed)
+            .filter(|(rule, removed)| {
+                let is_style = match &rule.kind {
+                    CssRuleKind::Style => true,
+                    CssRuleKind::AtRule(name) => GROUPING_AT_RULES.contains(&name.as_str()),
+                    CssRuleKind::Keyframe => false,
+                };
+                is_style && !**removed
+            })
+            .flat_map(|(rule, _)| rule.declarations.iter())
+            .filter(|declaration| is_reference_declaration(&declaration.name))
+            .map(|declaration| declaration.value.clone())
+            .collect()
+    }
+}
+
+fn is_reference_declaration(name: &str) -> bool {
+    name.starts_with("animation") || name.starts_with("font") || name.starts_with("--")
+}
+
+/// Values of the `style` attributes of `pages` that may name an animation or a font
+fn inline_reference_values(pages: &[Page]) -> Vec<String> {
+    let style_selector = Selector::parse("[style]").unwrap();
+    pages.iter()
+        .flat_map(|page| page.document.select(&style_selector))
+        .filter_map(|element| element.value().attr("style"))
+        .flat_map(parse_style_attribute)
+        .filter(|declaration| is_reference_declaration(&declaration.name))
+        .map(|declaration| declaration.value)
+        .collect()
+}
+
+/// Remove the unused rules of `css`, along with the `@keyframes` and `@font-face` rules
+/// none of `reference_values` refers to. Fonts and animations may be declared in one
+/// stylesheet and used from another, so the values come from all the page stylesheets
+/// and `style` attributes.
+pub fn purge_stylesheet(css: &str, evaluated: &EvaluatedStylesheet, reference_values: &[String]) -> PurgedStylesheet {
+    let rules = &evaluated.rules;
+    let mut removed = evaluated.removed.clone();
+
+    for index in 0..rules.len() {
+        if removed[index] {
+            continue;
+        }
+        let referenced_name = match &rules[index].kind {
+            CssRuleKind::AtRule(name) if name.ends_with("keyframes") => Some(rules[index].prelude.clone()),
+            CssRuleKind::AtRule(name) if name == "font-face" => font_face_family(&rules[index]),
+            _ => None,
+        };
+        if let Some(referenced_name) = referenced_name && !is_referenced(&referenced_name, reference_values) {
+            removed[index] = true;
+            for child in index + 1..rules.len() {
+                if rules[child].parent.is_some_and(|parent| removed[parent]) {
+                    removed[child] = true;
+                }
+            }
+        }
+    }
+
+    // Edits as (range, replacement), outermost removed rules only
+    let mut edits: Vec<(std::ops::Range<usize>, String)> = Vec::new();
+    let mut removed_rules = 0;
+    for (index, rule) in rules.iter().enumerate() {
+        let parent_removed = rule.parent.is_some_and(|parent| removed[parent]);
+        if parent_removed {
+            continue;
+        }
+        if removed[index] {
+            removed_rules += 1;
+            edits.push((rule.range.start..skip_trailing_whitespace(css, rule.range.end), String::new()));
+        } else if let RuleUsage::PartiallyUsed(unused_items) = &evaluated.usages[index] {
+            let items: Vec<String> = split_selector_list(&rule.prelude)
+                .into_iter()
+                .enumerate()
+                .filter(|(i, _)| !unused_items.contains(i))
+                .map(|(_, item)| item)
+                .collect();
+            edits.push((rule.range.start..rule.prelude_end, format!("{} ", items.join(", "))));
+        }
+    }
+
+    let mut purged = String::with_capacity(css.len());
+    let mut copied_up_to = 0;
+    for (range, replacement) in edits {
+        purged.push_str(&css[copied_up_to..range.start]);
+        purged.push_str(&replacement);
+        copied_up_to = range.end;
+    }
+    purged.push_str(&css[copied_up_to..]);
+
+    PurgedStylesheet {
+        css: purged,
+        removed_rules,
+    }
+}
+
+fn font_face_family(rule: &CssRule) -> Option<String> {
+    rule.declarations.iter()
+        .rfind(|declaration| declaration.name == "font-family")
+        .map(|declaration| declaration.value.trim_matches(['"', '\'']).to_string())
+}
+
+fn is_referenced(name: &str, values: &[String]) -> bool {
+    let name_re = Regex::new(&format!(r#"(?i)(^|[\s,"']){}($|[\s,"'!])"#, regex::escape(name))).unwrap();
+    values.iter().any(|value| name_re.is_match(value))
+}
+
+/// Whitespace up to the end of the line is removed with a rule
+fn skip_trailing_whitespace(css: &str, end: usize) -> usize {
+    let rest = &css[end..];
+    let line_end = rest.find('\n').map(|i| i + 1).unwrap_or(rest.len());
+    if rest[..line_end].trim().is_empty() {
+        end + line_end
+    } else {
+        end
+    }
+}
+
+/// Write a purged copy of each local stylesheet linked by the page, as `<name>.purged.css`,
+/// and point the page at it. `pages` starts with the page itself, the other pages of the site
+/// keep the rules they use.
+pub fn purge_css(pages: &[Page], file_path: &str, stylesheets: &[Stylesheet], safelist: &Safelist) {
+    use colored::*;
+
+    println!("\n{}", "✂️  Purging unused CSS...".cyan().bold());
+
+    let mut purged_urls: Vec<(String, String)> = Vec::new();
+    let mut seen: HashSet<String> = HashSet::new();
+    let mut total_original = 0;
+    let mut total_purged = 0;
+
+    let evaluated: Vec<EvaluatedStylesheet> = stylesheets.iter()
+        .map(|stylesheet| evaluate_stylesheet(&stylesheet.content, pages, safelist))
+        .collect();
+    let reference_values: Vec<String> = evaluated.iter()
+        .flat_map(|evaluated| evaluated.reference_values())
+        .chain(inline_reference_values(pages))
+        .collect();
+
+    for (stylesheet, evaluated) in stylesheets.iter().zip(&evaluated) {
+        if stylesheet.inline || !seen.insert(stylesheet.url.clone()) {
+            continue;
+        }
+        if is_remote_url(&stylesheet.url) {
+            println!("  ⏭️  Skipping remote stylesheet: {}", stylesheet.url);
+            continue;
+        }
+        // The `@import` rule pointing at it would have to be rewritten as well
+        if let Some(parent) = &stylesheet.imported_by {
+            println!("  ⏭️  Skipping stylesheet imported by {}: {}", parent, stylesheet.url);
+            continue;
+        }
+
+        let purged = purge_stylesheet(&stylesheet.content, evaluated, &reference_values);
+
+        let path = Path::new(&stylesheet.url);
+        let file_stem = path.file_stem()
+            .and_then(|s| s.to_str())
+            .unwrap_or("style");
+        let output_path = path.with_file_name(format!("{}.purged.css", file_stem));
+
+        if let Err(e) = fs::write(&output_path, &purged.css) {
+            println!("  ❌ Failed to write {}: {}", output_path.display(), e);
+            continue;
+        }
+
+        let original_size = stylesheet.content.len();
+        let purged_size = purged.css.len();
+        let saved = original_size.saturating_sub(purged_size);
+        let saved_percent = if original_size > 0 {
+            (saved as f64 / original_size as f64) * 100.0
+        } else {
+            0.0
+        };
+        println!("  ✅ {} -> {} ({} rules removed, {} -> {} bytes, saved {} bytes, {:.1}%)",
+                 stylesheet.url, output_path.display(), purged.removed_rules,
+                 original_size, purged_size, saved, saved_percent);
+
+        total_original += original_size;
+        total_purged += purged_size;
+        purged_urls.push((stylesheet.url.clone(), output_path.to_string_lossy().to_string()));
+    }
+
+    if !purged_urls.is_empty() {
+        change_html_stylesheet_urls(file_path, &purged_urls);
+    }
+
+    let saved = total_original.saturating_sub(total_purged);
+    println!("   Total CSS: {} bytes -> {} bytes", total_original, total_purged);
+    println!("   Saved: {} bytes", saved.to_string().green().bold());
+}
@@ -0,0 +1,200 @@
+use allsorts::binary::read::ReadScope;
+use allsorts::font::{Font, MatchingPresentation};
+use allsorts::font_data::FontData;
+use allsorts::subset::{CmapTarget, SubsetProfile, subset};
+use scraper::{Html, Selector};
+use std::collections::{BTreeSet, HashSet};
+use std::fs;
+use std::path::Path;
+use crate::css_analyzer::{Stylesheet, extract_font_faces};
+use crate::resource_extractor::FetchedResources;
+use crate::url_resolver::is_remote_url;
+use crate::woff2_encoder::encode_woff2;
+
+pub struct FontAudit {
+    pub url: String,
+    pub family: String,
+    pub format: String,
+    pub size: usize,
+    /// TTF/OTF/WOFF served where WOFF2 would be smaller
+    pub legacy_format: bool,
+    /// The `@font-face` has a WOFF2 source, but after the one browsers download
+    pub woff2_listed_later: bool,
+    /// WOFF2 subset holding only the glyphs used by the page text
+    pub subset: Option<Vec<u8>>,
+    pub error: Option<String>,
+}
+
+pub struct FontAnalysis {
+    pub fonts: Vec<FontAudit>,
+    /// Distinct characters of the page text the subsets are built from
+    pub used_characters: usize,
+    pub total_bytes: usize,
+    pub total_savings: usize,
+}
+
+pub async fn analyze_fonts(document: &Html, stylesheets: &[Stylesheet], fetched: &FetchedResources) -> FontAnalysis {
+    let characters = collect_page_characters(document);
+    let mut fonts: Vec<FontAudit> = Vec::new();
+    let mut seen: HashSet<String> = HashSet::new();
+
+    for stylesheet in stylesheets {
+        for font_face in extract_font_faces(&stylesheet.content, &stylesheet.url) {
+            let source = font_face.loaded_source();
+            // A WOFF2 source declared after the loaded one is never downloaded
+            let woff2_listed_later = !source.format.starts_with("woff2")
+                && font_face.sources.iter().any(|source| source.format.starts_with("woff2"));
+            if !seen.insert(source.url.clone()) {
+                continue;
+            }
+
+            let content = match fetched.get(&source.url).await {
+                Ok(content) => content,
+                Err(e) => {
+                    fonts.push(FontAudit {
+                        url: source.url.clone(),
+                        family: font_face.family.clone(),
+                        legacy_format: false,
+                        woff2_listed_later,
+                        format: source.format.clone(),
+                        size: 0,
+                        subset: None,
+                        error: Some(format!("failed to fetch: {}", e)),
+                    });
+                    continue;
+                }
+            };
+
+            let (subset, error) = match subset_font(&content, &characters) {
+                Ok(subset) => (Some(subset), None),
+                Err(e) => (None, Some(e)),
+            };
+
+            fonts.push(FontAudit {
+                url: source.url.clone(),
+                family: font_face.family.clone(),
+                legacy_format: matches!(source.format.as_str(), "truetype" | "opentype" | "woff" | "embedded-opentype"),
+                woff2_listed_later,
+                format: source.format.clone(),
+                size: content.len(),
+                subset,
+                error,
+            });
+        }
+    }
+
+    let total_bytes = fonts.iter().map(|font| font.size).sum();
+    let total_savings = fonts.iter()
+        .filter_map(|font| font.subset.as_ref().map(|subset| font.size.saturating_sub(subset.len())))
+        .sum();
+
+    FontAnalysis {
+        fonts,
+        used_characters: characters.len(),
+        total_bytes,
+        total_savings,
+    }
+}
+
+/// Characters a web font may have to render on this page
+fn collect_page_characters(document: &Html) -> BTreeSet<char> {
+    let mut characters: BTreeSet<char> = BTreeSet::new();
+
+    let body_selector = Selector::parse("body").unwrap();
+    let skipped_selector = Selector::parse("script, style, noscript, template").unwrap();
+    let skipped: HashSet<_> = document.select(&skipped_selector)
+        .flat_map(|element| element.descendants().map(|node| node.id()))
+        .collect();
+
+    for body in document.select(&body_selector) {
+        for node in body.descendants() {
+            if skipped.contains(&node.id()) {
+                continue;
+            }
+            if let Some(text) = node.value().as_text() {
+                characters.extend(text.chars());
+            }
+        }
+    }
+
+    // Text shown in form controls
+    let control_selector = Selector::parse("[placeholder], input[value], button[value]").unwrap();
+    for element in document.select(&control_selector) {
+        for attribute in ["placeholder", "value"] {
+            if let Some(value) = element.value().attr(attribute) {
+                characters.extend(value.chars());
+            }
+        }
+    }
+
+    // `text-transform` may change the case of any of them
+    let cased: Vec<char> = characters.iter()
+        .flat_map(|c| c.to_uppercase().chain(c.to_lowercase()))
+        .collect();
+    characters.extend(cased);
+
+    characters.retain(|c| !c.is_control());
+    characters.insert(' ');
+    characters.insert('\u{a0}');
+    characters
+}
+
+/// Subset a TTF/OTF/WOFF/WOFF2 font to `characters` and encode it as WOFF2.
+/// Layout tables (kerning, ligatures) are not kept in the subset.
+pub fn subset_font(content: &[u8], characters: &BTreeSet<char>) -> Result<Vec<u8>, String> {
+    let font_data = ReadScope::new(content)
+        .read::<FontData>()
+        .map_err(|e| format!("unsupported font: {}", e))?;
+    let provider = font_data.table_provider(0)
+        .map_err(|e| format!("unsupported font: {}", e))?;
+    let mut font = Font::new(provider).map_err(|e| format!("unsupported font: {}", e))?;
+
+    // The .notdef glyph must come first
+    let mut glyph_ids: BTreeSet<u16> = BTreeSet::new();
+    for character in characters {
+        let (glyph_id, _) = font.lookup_glyph_index(*character, MatchingPresentation::NotRequired, None);
+        if glyph_id != 0 {
+            glyph_ids.insert(glyph_id);
+        }
+    }
+    let glyph_ids: Vec<u16> = std::iter::once(0).chain(glyph_ids).collect();
+
+    // Browsers reject fonts with only a Mac Roman cmap
+    let sfnt = subset(&font.font_table_provider, &glyph_ids, &SubsetProfile::Minimal, CmapTarget::Unicode)
+        .map_err(|e| format!("subsetting failed: {}", e))?;
+    encode_woff2(&sfnt)
+}
+
+/// Write the subsetted fonts of local font files next to the originals, as `<name>.subset.woff2`
+pub fn write_font_subsets(analysis: &FontAnalysis) {
+    use colored::*;
+
+    println!("\n{}", "🔤 Writing subsetted fonts...".cyan().bold());
+
+    let mut written = 0;
+    for font in &analysis.fonts {
+        let Some(subset) = &font.subset else { continue };
+        if is_remote_url(&font.url) {
+            println!("  ⏭️  Skipping remote font: {}", font.url);
+            continue;
+        }
+
+        let path = Path::new(&font.url);
+        let file_stem = path.file_stem()
+            .and_then(|s| s.to_str())
+            .unwrap_or("font");
+        let output_path = path.with_file_name(format!("{}.subset.woff2", file_stem));
+
+        match fs::write(&output_path, subset) {
+            Ok(_) => {
+                written += 1;
+                println!("  ✅ {} -> {} ({} -> {} bytes)", font.url, output_path.display(), font.size, subset.len());
+            },
+            Err(e) => {
+                println!("  ❌ Failed to write {}: {}", output_path.display(), e);
+            }
+        }
+    }
+
+    println!("   Fonts written: {}", written.to_string().green().bold());
+}
@@ -1,6 +1,13 @@
 use minify_html::{Cfg, minify};
+use regex::Regex;
+use std::collections::HashMap;
 use std::fs;
+use std::ops::Range;
 use std::path::Path;
+use crate::image_analyzer::get_intrinsic_dimensions;
+use crate::image_converter::ResponsiveImage;
+use crate::lazy_loading::LoadingHint;
+use crate::url_resolver::resolve_url;
 
 pub fn minify_html_content(html: &str, url: &str) {
     let cfg = Cfg { ..Cfg::default() };
@@ -18,7 +25,7 @@ pub fn minify_html_content(html: &str, url: &str) {
         .unwrap_or(Path::new("."));
     
     let output_path = parent_dir.join(format!("{}.{}", file_stem, extension));
-    fs::remove_file(original_path).expect(&format!("Failed to delete {} file", original_path.display()));
+    fs::remove_file(original_path).unwrap_or_else(|_| panic!("Failed to delete {} file", original_path.display()));
 
     std::fs::write(&output_path, minified).expect("Failed to write minified file");
     
@@ -36,57 +43,428 @@ pub fn minify_html_content(html: &str, url: &str) {
     println!("   Saved: {} bytes ({:.1}%)", saved, saved_percent);
 }
 
-pub fn change_html_image_urls(file_path: &str, converted_urls: &Vec<String>) {
+pub fn change_html_image_urls(file_path: &str, converted_images: &[(String, String)]) {
     // Read the current file content
     let html = fs::read_to_string(file_path)
-        .expect(&format!("Failed to read file: {}", file_path));
+        .unwrap_or_else(|_| panic!("Failed to read file: {}", file_path));
     
     let mut modified_html = html.clone();
     
-    for converted_url in converted_urls {
+    for (source, webp_path) in converted_images {
         // Get just the filenames for replacement
-        let (original_filename, new_filename) = get_filenames(converted_url);
+        let (original_filename_re, new_filename) = get_filenames(source, webp_path);
         
-        modified_html = modified_html.replace(&original_filename, &new_filename);
+        modified_html = original_filename_re.replace_all(&modified_html, |cap: &regex::Captures| {
+            format!("{}{}{}", &cap[1], new_filename, &cap[2])
+        }).to_string();
     }
         
     // Write the modified content back to the file
     fs::write(file_path, &modified_html)
-        .expect(&format!("Failed to write to file: {}", file_path));
+        .unwrap_or_else(|_| panic!("Failed to write to file: {}", file_path));
     
-    println!("✅ Updated {} image URLs in {}", converted_urls.len(), file_path);
+    println!("✅ Updated {} image URLs in {}", converted_images.len(), file_path);
 }
 
-fn get_filenames(webp_path: &str) -> (String, String) {
-    let path = Path::new(webp_path);
-    
+fn get_filenames(source: &str, webp_path: &str) -> (Regex, String) {
     // Get the new filename (e.g., "orNoir.webp")
-    let new_filename = path.file_name()
+    let new_filename = Path::new(webp_path).file_name()
         .and_then(|f| f.to_str())
         .unwrap_or(webp_path)
         .to_string();
     
-    // Get the base name without extension (e.g., "orNoir")
-    let base_name = path.file_stem()
+    // Get the original filename (e.g., "orNoir.png"), other variants of the image were not converted
+    let original_filename = Path::new(source).file_name()
         .and_then(|f| f.to_str())
-        .unwrap_or("");
+        .unwrap_or(source);
     
-    // Try to find the original extension
-    let extensions = [".jpeg", ".jpg", ".png", ".gif", ".bmp", ".tiff"];
-    let parent_dir = path.parent().unwrap_or(Path::new("."));
+    let original_filename_re = Regex::new(&format!(
+        r#"(?i)(^|[/\s"'=(]){}([\s"'?#),]|$)"#,
+        regex::escape(original_filename)
+    )).unwrap();
     
-    for ext in extensions {
-        let potential_path = parent_dir.join(format!("{}{}", base_name, ext));
-        let original_filename = format!("{}{}", base_name, ext);
-        
-        // Check if this was the original file (now deleted) or just try common extensions
-        // Since original is deleted, we just try each extension
-        if webp_path.contains(base_name) {
-            // Return the first match that makes sense
-            return (original_filename, new_filename);
+    (original_filename_re, new_filename)
+}
+
+/// An attribute of an HTML start tag, `range` covering its name and value
+struct TagAttribute {
+    name: String,
+    value: Option<String>,
+    range: Range<usize>,
+}
+
+/// Split a single HTML start tag into its attributes. Quoted values are read whole,
+/// so a name written inside another value (`alt="Full width banner"`) is not an attribute.
+fn parse_tag_attributes(tag: &str) -> Vec<TagAttribute> {
+    let attr_re = Regex::new(r#"([^\s"'<>/=]+)(?:\s*=\s*(?:"([^"]*)"|'([^']*)'|([^\s"'<>`]+)))?"#).unwrap();
+    // Attributes start after the tag name
+    let name_end = tag.find(|c: char| c.is_whitespace() || c == '>' || c == '/').unwrap_or(tag.len());
+
+    attr_re.captures_iter(&tag[name_end..])
+        .map(|cap| {
+            let whole = cap.get(0).unwrap();
+            TagAttribute {
+                name: cap[1].to_lowercase(),
+                value: cap.get(2).or(cap.get(3)).or(cap.get(4)).map(|m| m.as_str().to_string()),
+                range: name_end + whole.start()..name_end + whole.end(),
+            }
+        })
+        .collect()
+}
+
+/// Read an attribute value from a single HTML start tag, an empty string for a boolean attribute
+pub fn get_tag_attribute(tag: &str, name: &str) -> Option<String> {
+    parse_tag_attributes(tag).into_iter()
+        .find(|attribute| attribute.name.eq_ignore_ascii_case(name))
+        .map(|attribute| attribute.value.unwrap_or_default())
+}
+
+/// Set an attribute on a single HTML start tag, replacing any existing value
+pub fn set_tag_attribute(tag: &str, name: &str, value: &str) -> String {
+    let new_attr = format!("{}=\"{}\"", name, value);
+
+    if let Some(attribute) = parse_tag_attributes(tag).into_iter().find(|attribute| attribute.name.eq_ignore_ascii_case(name)) {
+        return format!("{}{}{}", &tag[..attribute.range.start], new_attr, &tag[attribute.range.end..]);
+    }
+
+    // Insert before the closing `>` or `/>`
+    let insert_at = if tag.ends_with("/>") { tag.len() - 2 } else { tag.len() - 1 };
+    let (start, end) = tag.split_at(insert_at);
+    format!("{} {}{}", start.trim_end(), new_attr, if end == "/>" { " />" } else { end })
+}
+
+/// Apply `rewrite` to every `<tag_name>` start tag of the file, passing the resolved value of `url_attribute`
+fn rewrite_tags<F>(html: &str, file_path: &str, tag_name: &str, url_attribute: &str, rewrite: F) -> String
+where
+    F: FnMut(&str, &str) -> Option<String>,
+{
+    rewrite_tags_outside(html, file_path, tag_name, url_attribute, &[], rewrite)
+}
+
+/// Same as `rewrite_tags`, leaving untouched the tags that start inside one of the `skipped` ranges
+fn rewrite_tags_outside<F>(html: &str, file_path: &str, tag_name: &str, url_attribute: &str, skipped: &[Range<usize>], mut rewrite: F) -> String
+where
+    F: FnMut(&str, &str) -> Option<String>,
+{
+    // A `>` inside a quoted value does not close the tag
+    let tag_re = Regex::new(&format!(r#"(?i)<{}\b(?:[^>"']|"[^"]*"|'[^']*')*>"#, tag_name)).unwrap();
+
+    tag_re.replace_all(html, |cap: &regex::Captures| {
+        let tag = &cap[0];
+        let start = cap.get(0).unwrap().start();
+        if skipped.iter().any(|range| range.contains(&start)) {
+            return tag.to_string();
         }
+        get_tag_attribute(tag, url_attribute)
+            .and_then(|url| rewrite(tag, &resolve_url(file_path, &url)))
+            .unwrap_or_else(|| tag.to_string())
+    }).to_string()
+}
+
+/// Byte ranges of the comments and of the `<noscript>`, `<template>`, `<script>` and `<style>`
+/// elements: the parsed document has no elements there, their content is text or a separate fragment
+fn inert_ranges(html: &str) -> Vec<Range<usize>> {
+    let start_re = Regex::new(r#"(?i)<!--|<(noscript|template|script|style)\b(?:[^>"']|"[^"]*"|'[^']*')*>"#).unwrap();
+    let template_re = Regex::new(r"(?i)<(/?)template\b[^>]*>").unwrap();
+    let mut ranges = Vec::new();
+    let mut from = 0;
+
+    while let Some(cap) = start_re.captures_at(html, from) {
+        let start = cap.get(0).unwrap();
+        let end = match cap.get(1).map(|name| name.as_str().to_ascii_lowercase()) {
+            None => html[start.end()..].find("-->").map(|index| start.end() + index + 3),
+            // Templates can be nested, the other elements hold raw text up to their closing tag
+            Some(name) if name == "template" => {
+                let mut depth = 1;
+                template_re.captures_iter(&html[start.end()..]).find_map(|tag| {
+                    depth += if tag[1].is_empty() { 1 } else { -1 };
+                    (depth == 0).then(|| start.end() + tag.get(0).unwrap().end())
+                })
+            },
+            Some(name) => Regex::new(&format!(r"(?i)</{}\s*>", name)).unwrap()
+                .find_at(html, start.end())
+                .map(|closing| closing.end()),
+        }.unwrap_or(html.len());
+
+        ranges.push(start.start()..end);
+        from = end;
     }
-    
-    // Default fallback: assume .jpeg
-    (format!("{}.jpeg", base_name), new_filename)
-}
\ No newline at end of file
+
+    ranges
+}
+
+/// Point the `<link>` tags of the file at new stylesheets, given as (resolved original path, new path)
+pub fn change_html_stylesheet_urls(file_path: &str, stylesheet_urls: &[(String, String)]) {
+    let html = fs::read_to_string(file_path)
+        .unwrap_or_else(|_| panic!("Failed to read file: {}", file_path));
+
+    let mut updated = 0;
+    let modified_html = rewrite_tags(&html, file_path, "link", "href", |tag, css_path| {
+        let (_, new_path) = stylesheet_urls.iter().find(|(original, _)| original == css_path)?;
+        let href = get_tag_attribute(tag, "href")?;
+
+        // Keep the directory part of `href`, the new file lives next to the original
+        let href_dir = match href.rfind('/') {
+            Some(index) => &href[..=index],
+            None => "",
+        };
+        let new_file_name = Path::new(new_path).file_name()
+            .and_then(|f| f.to_str())
+            .unwrap_or(new_path);
+
+        updated += 1;
+        Some(set_tag_attribute(tag, "href", &format!("{}{}", href_dir, new_file_name)))
+    });
+
+    fs::write(file_path, &modified_html)
+        .unwrap_or_else(|_| panic!("Failed to write to file: {}", file_path));
+
+    println!("✅ Updated {} stylesheet links in {}", updated, file_path);
+}
+
+/// Inline `critical_css` before the first of the `deferred_urls` stylesheet links, and turn those
+/// links into preloads applied once loaded, with a `<noscript>` fallback
+pub fn inline_critical_css_in_html(file_path: &str, critical_css: &str, deferred_urls: &[String]) {
+    let html = fs::read_to_string(file_path)
+        .unwrap_or_else(|_| panic!("Failed to read file: {}", file_path));
+
+    let mut updated = 0;
+    let modified_html = rewrite_tags(&html, file_path, "link", "href", |tag, css_path| {
+        let rel = get_tag_attribute(tag, "rel")?;
+        if !rel.eq_ignore_ascii_case("stylesheet") || !deferred_urls.iter().any(|url| url == css_path) {
+            return None;
+        }
+
+        let preload = set_tag_attribute(tag, "rel", "preload");
+        let preload = set_tag_attribute(&preload, "as", "style");
+        let preload = set_tag_attribute(&preload, "onload", "this.onload=null;this.rel='stylesheet'");
+        let style = if updated == 0 {
+            format!("<style>{}</style>", critical_css)
+        } else {
+            String::new()
+        };
+
+        updated += 1;
+        Some(format!("{}{}<noscript>{}</noscript>", style, preload, tag))
+    });
+
+    fs::write(file_path, &modified_html)
+        .unwrap_or_else(|_| panic!("Failed to write to file: {}", file_path));
+
+    println!("✅ Inlined critical CSS and deferred {} stylesheets in {}", updated, file_path);
+}
+
+pub fn add_srcset_to_images(file_path: &str, responsive_images: &[ResponsiveImage]) {
+    let html = fs::read_to_string(file_path)
+        .unwrap_or_else(|_| panic!("Failed to read file: {}", file_path));
+
+    let mut updated = 0;
+    let modified_html = rewrite_tags(&html, file_path, "img", "src", |tag, image_path| {
+        let responsive = responsive_images.iter().find(|r| r.source == image_path)?;
+        let src = get_tag_attribute(tag, "src")?;
+
+        // Variants live next to the source, so reuse the directory part of `src`
+        let src_dir = match src.rfind('/') {
+            Some(index) => &src[..=index],
+            None => "",
+        };
+        let file_name = |path: &str| {
+            Path::new(path).file_name()
+                .and_then(|f| f.to_str())
+                .unwrap_or(path)
+                .to_string()
+        };
+
+        let mut candidates: Vec<String> = responsive.variants.iter()
+            .map(|(width, path)| format!("{}{} {}w", src_dir, file_name(path), width))
+            .collect();
+        candidates.push(format!("{} {}w", src, responsive.source_width));
+
+        // Without a declared width the image is assumed to span the viewport
+        let sizes = match get_tag_attribute(tag, "width").and_then(|w| w.parse::<u32>().ok()) {
+            Some(width) => format!("(max-width: {}px) 100vw, {}px", width, width),
+            None => "100vw".to_string(),
+        };
+
+        updated += 1;
+        let tag = set_tag_attribute(tag, "srcset", &candidates.join(", "));
+        Some(set_tag_attribute(&tag, "sizes", &sizes))
+    });
+
+    fs::write(file_path, &modified_html)
+        .unwrap_or_else(|_| panic!("Failed to write to file: {}", file_path));
+
+    println!("✅ Added srcset to {} images in {}", updated, file_path);
+}
+
+/// Add `loading="lazy"` and `decoding="async"` to the elements below the fold and
+/// `fetchpriority="high"` to the hero image
+pub fn add_loading_attributes(file_path: &str, hints: &[LoadingHint]) {
+    let mut html = fs::read_to_string(file_path)
+        .unwrap_or_else(|_| panic!("Failed to read file: {}", file_path));
+
+    let mut lazy_counts = [0, 0];
+    for (tag_index, tag_name) in ["img", "iframe"].iter().enumerate() {
+        // The hints count the elements of the parsed document, which has none in these ranges
+        let skipped = inert_ranges(&html);
+        let mut occurrences: HashMap<String, usize> = HashMap::new();
+        html = rewrite_tags_outside(&html, file_path, tag_name, "src", &skipped, |tag, url| {
+            let occurrence = occurrences.entry(url.to_string()).or_insert(0);
+            let hint = hints.iter().find(|hint| hint.tag_name == *tag_name && hint.url == url && hint.occurrence == *occurrence);
+            *occurrence += 1;
+            let hint = hint?;
+
+            if hint.hero {
+                let tag = set_tag_attribute(tag, "fetchpriority", "high");
+                // A lazy-loaded hero image waits for the layout before downloading
+                if get_tag_attribute(&tag, "loading").is_some_and(|loading| loading.eq_ignore_ascii_case("lazy")) {
+                    return Some(set_tag_attribute(&tag, "loading", "eager"));
+                }
+                return Some(tag);
+            }
+            if !hint.lazy {
+                return None;
+            }
+
+            lazy_counts[tag_index] += 1;
+            let tag = set_tag_attribute(tag, "loading", "lazy");
+            if *tag_name == "img" && get_tag_attribute(&tag, "decoding").is_none() {
+                return Some(set_tag_attribute(&tag, "decoding", "async"));
+            }
+            Some(tag)
+        });
+    }
+
+    fs::write(file_path, &html)
+        .unwrap_or_else(|_| panic!("Failed to write to file: {}", file_path));
+
+    println!("✅ Lazy-loaded {} images and {} iframes in {}", lazy_counts[0], lazy_counts[1], file_path);
+}
+
+/// Add the `width`/`height` attributes missing on `<img>` tags from the intrinsic dimensions of
+/// the local images, so the browser reserves their space before they load. With one side given,
+/// the other follows the aspect ratio.
+pub fn add_image_dimensions(file_path: &str) {
+    let html = fs::read_to_string(file_path)
+        .unwrap_or_else(|_| panic!("Failed to read file: {}", file_path));
+
+    let mut fixed: Vec<(String, u32, u32)> = Vec::new();
+    let modified_html = rewrite_tags(&html, file_path, "img", "src", |tag, image_path| {
+        let declared = |name: &str| get_tag_attribute(tag, name);
+        let (width, height) = (declared("width"), declared("height"));
+        if width.is_some() && height.is_some() {
+            return None;
+        }
+
+        let content = fs::read(image_path).ok()?;
+        let (intrinsic_width, intrinsic_height) = get_intrinsic_dimensions(&content)?;
+        let aspect_ratio = intrinsic_width as f64 / intrinsic_height.max(1) as f64;
+        // Percentages and other units cannot be turned into the other side
+        let parse = |value: &str| value.trim().trim_end_matches("px").parse::<f64>().ok().filter(|v| *v > 0.0);
+
+        let (width, height) = match (width, height) {
+            (None, None) => (intrinsic_width, intrinsic_height),
+            (Some(width), None) => {
+                let width = parse(&width)?;
+                (width.round() as u32, (width / aspect_ratio).round() as u32)
+            },
+            (None, Some(height)) => {
+                let height = parse(&height)?;
+                ((height * aspect_ratio).round() as u32, height.round() as u32)
+            },
+            (Some(_), Some(_)) => return None,
+        };
+
+        fixed.push((image_path.to_string(), width, height));
+        let tag = set_tag_attribute(tag, "width", &width.to_string());
+        Some(set_tag_attribute(&tag, "height", &height.to_string()))
+    });
+
+    if fixed.is_empty() {
+        println!("✅ All images of {} already have width and height attributes", file_path);
+        return;
+    }
+
+    fs::write(file_path, &modified_html)
+        .unwrap_or_else(|_| panic!("Failed to write to file: {}", file_path));
+
+    println!("✅ Added width/height to {} images in {}", fixed.len(), file_path);
+    for (image_path, width, height) in &fixed {
+        println!("   {} ({}x{})", image_path, width, height);
+    }
+    println!("   Images resized by CSS need `height: auto` to keep their aspect ratio");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn set_tag_attribute_ignores_names_inside_values() {
+        let tag = r#"<img src="banner.png" alt="Full width banner">"#;
+        let tag = set_tag_attribute(tag, "width", "800");
+        let tag = set_tag_attribute(&tag, "height", "600");
+        assert_eq!(tag, r#"<img src="banner.png" alt="Full width banner" width="800" height="600">"#);
+
+        let tag = r#"<img src="demo.png" title='loading="eager" demo' alt="Lazy loading demo">"#;
+        assert_eq!(set_tag_attribute(tag, "loading", "lazy"),
+                   r#"<img src="demo.png" title='loading="eager" demo' alt="Lazy loading demo" loading="lazy">"#);
+    }
+
+    #[test]
+    fn set_tag_attribute_ignores_longer_names() {
+        let tag = r#"<link hreflang="fr" href="style.css" rel="stylesheet">"#;
+        assert_eq!(set_tag_attribute(tag, "href", "style.purged.css"),
+                   r#"<link hreflang="fr" href="style.purged.css" rel="stylesheet">"#);
+        assert_eq!(set_tag_attribute(r#"<img srcset="a.webp 1x">"#, "src", "a.webp"),
+                   r#"<img srcset="a.webp 1x" src="a.webp">"#);
+    }
+
+    #[test]
+    fn set_tag_attribute_replaces_existing_values() {
+        assert_eq!(set_tag_attribute("<img src=a.png loading=lazy>", "loading", "eager"),
+                   r#"<img src=a.png loading="eager">"#);
+        assert_eq!(set_tag_attribute(r#"<img SRC='a.png' />"#, "src", "a.webp"), r#"<img src="a.webp" />"#);
+        assert_eq!(set_tag_attribute("<img async src=a.png>", "async", "true"), r#"<img async="true" src=a.png>"#);
+    }
+
+    #[test]
+    fn get_tag_attribute_ignores_names_inside_values() {
+        let tag = r#"<img alt="src=fake.png" title='width=10' src="real.png">"#;
+        assert_eq!(get_tag_attribute(tag, "src").as_deref(), Some("real.png"));
+        assert_eq!(get_tag_attribute(tag, "width"), None);
+        assert_eq!(get_tag_attribute(r#"<link hreflang="fr">"#, "href"), None);
+        assert_eq!(get_tag_attribute("<script defer src=app.js>", "defer").as_deref(), Some(""));
+    }
+
+    #[test]
+    fn get_filenames_only_matches_the_converted_source() {
+        let (original_filename_re, new_filename) = get_filenames("/site/img/photo.jpg", "/site/img/photo.webp");
+        let html = r#"<img src="img/photo.jpg"><img src="img/photo.png"><img src="img/bigphoto.jpg"><div style="background:url(photo.jpg?v=2)">"#;
+        let rewritten = original_filename_re.replace_all(html, |cap: &regex::Captures| format!("{}{}{}", &cap[1], new_filename, &cap[2]));
+        assert_eq!(rewritten, r#"<img src="img/photo.webp"><img src="img/photo.png"><img src="img/bigphoto.jpg"><div style="background:url(photo.webp?v=2)">"#);
+    }
+
+    #[test]
+    fn rewrite_tags_skips_closing_brackets_inside_values() {
+        let html = r#"<p><img alt="a > b" src="a.png"></p>"#;
+        let rewritten = rewrite_tags(html, "./index.html", "img", "src", |tag, _| Some(set_tag_attribute(tag, "loading", "lazy")));
+        assert_eq!(rewritten, r#"<p><img alt="a > b" src="a.png" loading="lazy"></p>"#);
+    }
+
+    #[test]
+    fn inert_ranges_cover_elements_missing_from_the_parsed_document() {
+        let html = concat!(
+            r#"<noscript><img src="a.png"></noscript><!-- <img src="a.png"> -->"#,
+            r#"<template><template></template><img src="a.png"></template>"#,
+            r#"<script>let s = '<img src="a.png">';</script><img src="a.png">"#,
+        );
+        let mut sources = Vec::new();
+        rewrite_tags_outside(html, "./index.html", "img", "src", &inert_ranges(html), |tag, _| {
+            sources.push(tag.to_string());
+            None
+        });
+        assert_eq!(sources, vec![r#"<img src="a.png">"#.to_string()]);
+        assert_eq!(inert_ranges(html).last().unwrap().end, html.len() - r#"<img src="a.png">"#.len());
+    }
+}
@@ -0,0 +1,138 @@
+use image::ImageDecoder;
+use image::metadata::Orientation;
+use scraper::{ElementRef, Html, Selector};
+use regex::Regex;
+use std::io::Cursor;
+use crate::resource_extractor::FetchedResources;
+use crate::url_resolver::resolve_url;
+
+/// An image is flagged when it is this many times wider (or taller) than displayed
+const OVERSIZE_RATIO: f64 = 2.0;
+/// Pixel density kept when estimating the resized image, so high-DPI screens stay sharp
+const TARGET_PIXEL_RATIO: f64 = 2.0;
+
+pub struct OversizedImage {
+    pub url: String,
+    pub intrinsic_width: u32,
+    pub intrinsic_height: u32,
+    pub displayed_width: u32,
+    pub displayed_height: u32,
+    pub size: usize,
+    pub estimated_savings: usize,
+}
+
+pub struct ImageSizeAnalysis {
+    pub checked_images: usize,
+    /// Images without width/height attributes or inline size, that could not be compared
+    pub unsized_images: usize,
+    pub oversized_images: Vec<OversizedImage>,
+    pub total_savings: usize,
+}
+
+pub async fn analyze_image_sizes(document: &Html, base_url: &str, fetched: &FetchedResources) -> ImageSizeAnalysis {
+    let mut checked_images = 0;
+    let mut unsized_images = 0;
+    let mut oversized_images: Vec<OversizedImage> = Vec::new();
+
+    let img_selector = Selector::parse("img[src]").unwrap();
+
+    for element in document.select(&img_selector) {
+        let Some(src) = element.value().attr("src") else { continue };
+        let image_url = resolve_url(base_url, src);
+
+        let Ok(content) = fetched.get(&image_url).await else { continue };
+        let Some((intrinsic_width, intrinsic_height)) = get_intrinsic_dimensions(&content) else { continue };
+        checked_images += 1;
+
+        let Some((displayed_width, displayed_height)) = get_displayed_dimensions(&element, intrinsic_width, intrinsic_height) else {
+            unsized_images += 1;
+            continue;
+        };
+
+        let width_ratio = intrinsic_width as f64 / displayed_width.max(1) as f64;
+        let height_ratio = intrinsic_height as f64 / displayed_height.max(1) as f64;
+        if width_ratio.max(height_ratio) < OVERSIZE_RATIO {
+            continue;
+        }
+
+        // Encoded size scales roughly with the pixel count
+        let target_width = (displayed_width as f64 * TARGET_PIXEL_RATIO).min(intrinsic_width as f64);
+        let target_height = (displayed_height as f64 * TARGET_PIXEL_RATIO).min(intrinsic_height as f64);
+        let kept_ratio = (target_width * target_height) / (intrinsic_width as f64 * intrinsic_height as f64);
+        let estimated_savings = (content.len() as f64 * (1.0 - kept_ratio)) as usize;
+
+        oversized_images.push(OversizedImage {
+            url: image_url,
+            intrinsic_width,
+            intrinsic_height,
+            displayed_width,
+            displayed_height,
+            size: content.len(),
+            estimated_savings,
+        });
+    }
+
+    // Biggest wins first
+    oversized_images.sort_by_key(|image| std::cmp::Reverse(image.estimated_savings));
+    let total_savings = oversized_images.iter().map(|image| image.estimated_savings).sum();
+
+    ImageSizeAnalysis {
+        checked_images,
+        unsized_images,
+        oversized_images,
+        total_savings,
+    }
+}
+
+/// Size the browser displays the image at, width and height swapped when the EXIF
+/// orientation rotates it a quarter turn (orientations 5 to 8)
+pub fn get_intrinsic_dimensions(content: &[u8]) -> Option<(u32, u32)> {
+    let mut decoder = image::ImageReader::new(Cursor::new(content))
+        .with_guessed_format()
+        .ok()?
+        .into_decoder()
+        .ok()?;
+    let (width, height) = decoder.dimensions();
+
+    match decoder.orientation().unwrap_or(Orientation::NoTransforms) {
+        Orientation::Rotate90 | Orientation::Rotate270 | Orientation::Rotate90FlipH | Orientation::Rotate270FlipH => Some((height, width)),
+        _ => Some((width, height)),
+    }
+}
+
+/// Displayed size from the `width`/`height` attributes or the inline style,
+/// deriving the missing side from the intrinsic aspect ratio
+fn get_displayed_dimensions(element: &ElementRef, intrinsic_width: u32, intrinsic_height: u32) -> Option<(u32, u32)> {
+    let style = element.value().attr("style").unwrap_or("");
+
+    let width = get_style_px(style, "width")
+        .or_else(|| element.value().attr("width").and_then(parse_px));
+    let height = get_style_px(style, "height")
+        .or_else(|| element.value().attr("height").and_then(parse_px));
+
+    let aspect_ratio = intrinsic_width as f64 / intrinsic_height.max(1) as f64;
+    match (width, height) {
+        (Some(w), Some(h)) => Some((w, h)),
+        (Some(w), None) => Some((w, (w as f64 / aspect_ratio).round() as u32)),
+        (None, Some(h)) => Some(((h as f64 * aspect_ratio).round() as u32, h)),
+        (None, None) => None,
+    }
+}
+
+fn get_style_px(style: &str, property: &str) -> Option<u32> {
+    let property_re = Regex::new(&format!(r"(?i)(?:^|;)\s*{}\s*:\s*([0-9.]+)px", property)).unwrap();
+    property_re.captures(style)
+        .and_then(|cap| cap[1].parse::<f64>().ok())
+        .map(|value| value.round() as u32)
+        .filter(|value| *value > 0)
+}
+
+/// Parse an HTML length attribute ("300" or "300px"), ignoring percentages
+fn parse_px(value: &str) -> Option<u32> {
+    value.trim()
+        .trim_end_matches("px")
+        .parse::<f64>()
+        .ok()
+        .map(|value| value.round() as u32)
+        .filter(|value| *value > 0)
+}
@@ -0,0 +1,68 @@
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::PathBuf;
+use crate::image_converter::EncodedImage;
+
+/// Default cache location, relative to the working directory
+pub const DEFAULT_CACHE_DIR: &str = ".green_optimizer_cache";
+
+/// Already-encoded images, keyed by the hash of the source content and the encoding settings.
+/// Each entry is a `<key>.webp` file plus a `<key>.meta` file describing it.
+pub struct ImageCache {
+    dir: PathBuf,
+}
+
+impl ImageCache {
+    pub fn open(dir: &str) -> Result<Self, std::io::Error> {
+        fs::create_dir_all(dir)?;
+        Ok(ImageCache { dir: PathBuf::from(dir) })
+    }
+
+    pub fn key(content: &[u8], settings: &str) -> String {
+        let mut hasher = Sha256::new();
+        hasher.update(settings.as_bytes());
+        hasher.update([0]);
+        hasher.update(content);
+        hasher.finalize()
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect()
+    }
+
+    pub fn get(&self, key: &str) -> Option<EncodedImage> {
+        let webp_data = fs::read(self.dir.join(format!("{}.webp", key))).ok()?;
+        let meta = fs::read_to_string(self.dir.join(format!("{}.meta", key))).ok()?;
+
+        let mut lines = meta.lines();
+        let label = lines.next()?.to_string();
+        let metadata_kept = lines.next()?.parse().ok()?;
+        let video_candidate_frames = lines.next().and_then(|frames| frames.parse().ok());
+
+        Some(EncodedImage {
+            webp_data,
+            label,
+            metadata_kept,
+            video_candidate_frames,
+        })
+    }
+
+    pub fn put(&self, key: &str, encoded: &EncodedImage) -> Result<(), std::io::Error> {
+        let meta = format!(
+            "{}\n{}\n{}\n",
+            encoded.label,
+            encoded.metadata_kept,
+            encoded.video_candidate_frames.map(|frames| frames.to_string()).unwrap_or_default()
+        );
+
+        // Write the image before its description, so a half-written entry is never read
+        self.write_atomically(&format!("{}.webp", key), &encoded.webp_data)?;
+        self.write_atomically(&format!("{}.meta", key), meta.as_bytes())
+    }
+
+    /// Write through a temporary file, as two workers may store the same entry at once
+    fn write_atomically(&self, file_name: &str, data: &[u8]) -> Result<(), std::io::Error> {
+        let temporary_path = self.dir.join(format!("{}.{:?}.tmp", file_name, std::thread::current().id()));
+        fs::write(&temporary_path, data)?;
+        fs::rename(&temporary_path, self.dir.join(file_name))
+    }
+}
@@ -1,111 +1,292 @@
+use std::collections::HashSet;
+use std::io::Cursor;
 use std::path::Path;
 use std::fs;
+use image::{DynamicImage, RgbaImage};
+use webp::{Encoder, WebPConfig};
+use crate::image_cache::ImageCache;
+use crate::resource_extractor::FetchedResources;
+use crate::image_metadata::{MetadataOptions, add_webp_metadata, copyright_exif, metadata_size};
 
-pub fn convert_images_to_webp(images_urls: &Vec<String>) -> Vec<String>{
-    use image::io::Reader as ImageReader;
-    use webp::Encoder;
+/// Bump whenever the encoding changes, so cached images are re-encoded
+const ENCODER_VERSION: u32 = 1;
+
+/// Above this many distinct colors an image is treated as a photo
+const MAX_PALETTE_COLORS: usize = 256;
+/// Share of pixels identical to their left neighbour above which an image is a flat graphic
+const FLAT_COLOR_RATIO: f64 = 0.6;
+
+/// WebP encoding strategy picked from the image content
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EncodingMode {
+    Lossy,
+    NearLossless,
+    Lossless,
+}
+
+impl EncodingMode {
+    pub fn label(&self) -> &'static str {
+        match self {
+            EncodingMode::Lossy => "lossy",
+            EncodingMode::NearLossless => "near-lossless",
+            EncodingMode::Lossless => "lossless",
+        }
+    }
+}
+
+/// What the pixels of an image look like, used to pick the encoding mode
+pub struct ImageProfile {
+    pub has_alpha: bool,
+    /// Every pixel has equal red, green and blue, as in L8/La8 images
+    pub grayscale: bool,
+    pub unique_colors: usize,
+    pub flat_ratio: f64,
+}
+
+pub fn profile_image(rgba: &RgbaImage) -> ImageProfile {
+    let mut has_alpha = false;
+    let mut grayscale = true;
+    let mut colors: HashSet<[u8; 4]> = HashSet::new();
+    let mut flat_pixels: u64 = 0;
+
+    for row in rgba.rows() {
+        let mut previous: Option<[u8; 4]> = None;
+        for pixel in row {
+            if pixel.0[3] < 255 {
+                has_alpha = true;
+            }
+            if pixel.0[0] != pixel.0[1] || pixel.0[1] != pixel.0[2] {
+                grayscale = false;
+            }
+            // Stop counting once we know it is not a palette image
+            if colors.len() <= MAX_PALETTE_COLORS {
+                colors.insert(pixel.0);
+            }
+            if previous == Some(pixel.0) {
+                flat_pixels += 1;
+            }
+            previous = Some(pixel.0);
+        }
+    }
+
+    let pixel_count = rgba.width() as u64 * rgba.height() as u64;
+    let flat_ratio = if pixel_count > 0 {
+        flat_pixels as f64 / pixel_count as f64
+    } else {
+        0.0
+    };
+
+    ImageProfile {
+        has_alpha,
+        grayscale,
+        unique_colors: colors.len(),
+        flat_ratio,
+    }
+}
+
+pub fn choose_encoding_mode(profile: &ImageProfile) -> EncodingMode {
+    // A grayscale photo never has more than 256 colors, only its flat areas tell it from a graphic
+    let is_graphic = profile.unique_colors <= MAX_PALETTE_COLORS
+        && (!profile.grayscale || profile.flat_ratio >= FLAT_COLOR_RATIO);
+    if is_graphic {
+        // Logos, icons and screenshots: lossless is both exact and smaller
+        EncodingMode::Lossless
+    } else if profile.has_alpha || profile.flat_ratio >= FLAT_COLOR_RATIO {
+        // Lossy alpha and flat areas show artifacts, near-lossless keeps them clean
+        EncodingMode::NearLossless
+    } else {
+        EncodingMode::Lossy
+    }
+}
+
+/// Encode an image to WebP, picking lossless/near-lossless/lossy from its content
+/// and dropping the alpha channel when the image is fully opaque.
+pub fn encode_webp(img: &DynamicImage) -> Result<(Vec<u8>, EncodingMode), String> {
+    let rgba = img.to_rgba8();
+    let (width, height) = rgba.dimensions();
+    let profile = profile_image(&rgba);
+    let mode = choose_encoding_mode(&profile);
+
+    let rgb;
+    let encoder = if profile.has_alpha {
+        Encoder::from_rgba(&rgba, width, height)
+    } else {
+        rgb = img.to_rgb8();
+        Encoder::from_rgb(&rgb, width, height)
+    };
+
+    let mut config = WebPConfig::new().map_err(|_| "Failed to initialize WebP config".to_string())?;
+    match mode {
+        EncodingMode::Lossy => {
+            // Quality 80 is a good balance
+            config.quality = 80.0;
+        },
+        EncodingMode::NearLossless => {
+            config.lossless = 1;
+            config.near_lossless = 60;
+            config.quality = 75.0;
+        },
+        EncodingMode::Lossless => {
+            config.lossless = 1;
+            config.quality = 75.0;
+        },
+    }
+
+    let webp_data = encoder.encode_advanced(&config)
+        .map_err(|e| format!("WebP encoding failed: {:?}", e))?;
+    Ok((webp_data.to_vec(), mode))
+}
+
+/// A decoded image, already rotated according to its EXIF orientation
+pub struct DecodedImage {
+    pub image: DynamicImage,
+    pub icc_profile: Option<Vec<u8>>,
+    pub exif: Option<Vec<u8>>,
+}
+
+pub fn decode_image(content: &[u8]) -> Result<DecodedImage, String> {
+    use image::{ImageDecoder, ImageReader};
+    use image::metadata::Orientation;
+
+    let reader = ImageReader::new(Cursor::new(content))
+        .with_guessed_format()
+        .map_err(|e| format!("failed to open: {}", e))?;
+    let mut decoder = reader.into_decoder().map_err(|e| format!("failed to decode: {}", e))?;
+
+    let orientation = decoder.orientation().unwrap_or(Orientation::NoTransforms);
+    let icc_profile = decoder.icc_profile().unwrap_or(None);
+    let exif = decoder.exif_metadata().unwrap_or(None);
+
+    let mut image = DynamicImage::from_decoder(decoder).map_err(|e| format!("failed to decode: {}", e))?;
+    // The orientation tag is not carried over, so the pixels must be rotated now
+    image.apply_orientation(orientation);
+
+    Ok(DecodedImage {
+        image,
+        icc_profile,
+        exif,
+    })
+}
+
+/// Result of converting one image file
+pub struct EncodedImage {
+    pub webp_data: Vec<u8>,
+    /// How the image was encoded, shown in the report
+    pub label: String,
+    /// Metadata bytes carried over to the WebP file
+    pub metadata_kept: usize,
+    /// Frame count of animations that would be smaller as a video
+    pub video_candidate_frames: Option<usize>,
+}
+
+/// Everything that changes the encoder output besides the source content, used as cache key
+fn encoding_settings(metadata_options: &MetadataOptions) -> String {
+    format!(
+        "webp-v{};keep_copyright={};keep_color_profile={}",
+        ENCODER_VERSION,
+        metadata_options.keep_copyright,
+        metadata_options.keep_color_profile
+    )
+}
+
+/// What happened to one image. Outcomes are reported once every image is done,
+/// so the report keeps the page order whatever the thread scheduling.
+enum ConversionOutcome {
+    Skipped(String),
+    Failed(String),
+    Converted(ConvertedImage),
+}
+
+struct ConvertedImage {
+    source: String,
+    output_path: String,
+    original_size: u64,
+    new_size: u64,
+    label: String,
+    metadata_removed: usize,
+    video_candidate_frames: Option<usize>,
+    from_cache: bool,
+}
+
+/// Convert local images to WebP on `threads` worker threads (0 uses one per CPU core),
+/// reusing the images already encoded in `cache`. Returns the (source, WebP path) of each converted image.
+pub fn convert_images_to_webp(images_urls: &[String], metadata_options: &MetadataOptions, threads: usize, cache: Option<&ImageCache>) -> Vec<(String, String)>{
     use colored::*;
+    use rayon::prelude::*;
     
     println!("\n{}", "🖼️  Converting images to WebP...".cyan().bold());
     
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(threads)
+        .build()
+        .expect("Failed to create the conversion thread pool");
+    // An image used twice on the page must only be converted by one worker
+    let mut seen: HashSet<&String> = HashSet::new();
+    let unique_images: Vec<&String> = images_urls.iter()
+        .filter(|image_path| seen.insert(*image_path))
+        .collect();
+    println!("   Using {} threads for {} images", pool.current_num_threads(), unique_images.len());
+    
+    // `collect` keeps the input order
+    let outcomes: Vec<ConversionOutcome> = pool.install(|| {
+        unique_images.par_iter()
+            .map(|image_path| convert_image(image_path, metadata_options, cache))
+            .collect()
+    });
+    
     let mut converted = 0;
     let mut total_saved: i64 = 0;
-    let mut converted_urls: Vec<String> = Vec::new();
+    let mut total_metadata_removed = 0;
+    let mut cache_hits = 0;
+    let mut converted_images: Vec<(String, String)> = Vec::new();
     
-    for image_path in images_urls {
-        // Skip remote URLs and non-image files
-        if image_path.starts_with("http") {
-            println!("  ⏭️  Skipping remote image: {}", image_path);
-            continue;
-        }
+    for outcome in outcomes {
+        let image = match outcome {
+            ConversionOutcome::Skipped(message) | ConversionOutcome::Failed(message) => {
+                println!("  {}", message);
+                continue;
+            },
+            ConversionOutcome::Converted(image) => image,
+        };
         
-        // Check if file exists and is a supported format
-        let path = Path::new(image_path);
-        if !path.exists() {
-            println!("  ⚠️  File not found: {}", image_path);
-            continue;
+        if let Some(frame_count) = image.video_candidate_frames {
+            println!("  🎞️  {} ({} frames) would be even smaller as an MP4/WebM <video>", 
+                image.source, 
+                frame_count);
         }
         
-        let extension = path.extension()
-            .and_then(|e| e.to_str())
-            .unwrap_or("")
-            .to_lowercase();
-        
-        // Skip if already WebP or unsupported format
-        if extension == "webp" {
-            println!("  ⏭️  Already WebP: {}", image_path);
-            continue;
+        let saved = image.original_size as i64 - image.new_size as i64;
+        total_saved += saved;
+        total_metadata_removed += image.metadata_removed;
+        converted += 1;
+        if image.from_cache {
+            cache_hits += 1;
         }
         
-        if !["jpg", "jpeg", "png", "gif", "bmp", "tiff"].contains(&extension.as_str()) {
-            println!("  ⏭️  Unsupported format: {}", image_path);
-            continue;
-        }
+        let saved_str = if saved > 0 {
+            format!("saved {} bytes", saved).green()
+        } else {
+            format!("increased {} bytes", -saved).red()
+        };
         
-        // Get original file size
-        let original_size = std::fs::metadata(path)
-            .map(|m| m.len())
-            .unwrap_or(0);
+        println!("  ✅ {} -> {} ({}, {}, {} bytes of metadata stripped){}", 
+            image.source, 
+            image.output_path,
+            image.label,
+            saved_str,
+            image.metadata_removed,
+            if image.from_cache { " [cached]" } else { "" });
         
-        // Load and convert the image
-        match ImageReader::open(path) {
-            Ok(reader) => {
-                match reader.decode() {
-                    Ok(img) => {
-                        let rgba = img.to_rgba8();
-                        let (width, height) = rgba.dimensions();
-                        
-                        // Create WebP encoder (quality 80 is a good balance)
-                        let encoder = Encoder::from_rgba(&rgba, width, height);
-                        let webp_data = encoder.encode(80.0);
-                        
-                        // Create output path
-                        let output_path = path.with_extension("webp");
-                        
-                        // Write WebP file
-                        match std::fs::write(&output_path, &*webp_data) {
-                            Ok(_) => {
-                                let new_size = webp_data.len() as u64;
-                                let saved = original_size as i64 - new_size as i64;
-                                total_saved += saved;
-                                converted += 1;
-                                
-                                let saved_str = if saved > 0 {
-                                    format!("saved {} bytes", saved).green()
-                                } else {
-                                    format!("increased {} bytes", -saved).red()
-                                };
-
-                                
-                                
-                                println!("  ✅ {} -> {} ({})", 
-                                    image_path, 
-                                    output_path.display(),
-                                    saved_str);
-
-                                fs::remove_file(image_path).expect(&format!("Failed to delete {} file", image_path));
-                                converted_urls.push(output_path.to_string_lossy().to_string());
-                            },
-                            Err(e) => {
-                                println!("  ❌ Failed to write {}: {}", output_path.display(), e);
-                            }
-                        }
-                    },
-                    Err(e) => {
-                        println!("  ❌ Failed to decode {}: {}", image_path, e);
-                    }
-                }
-            },
-            Err(e) => {
-                println!("  ❌ Failed to open {}: {}", image_path, e);
-            }
-        }
+        converted_images.push((image.source, image.output_path));
     }
     
     // Summary
     println!("\n{}", "═══════════════════════════════════════".cyan());
     println!("📊 Conversion summary:");
     println!("   Images converted: {}", converted.to_string().green().bold());
+    if cache.is_some() {
+        println!("   Reused from cache: {}", cache_hits.to_string().green());
+    }
     
     if total_saved > 0 {
         println!("   Total space saved: {} bytes ({:.2} KB)", 
@@ -115,8 +296,508 @@ pub fn convert_images_to_webp(images_urls: &Vec<String>) -> Vec<String>{
         println!("   Total space increased: {} bytes", 
             (-total_saved).to_string().red().bold());
     }
+    if total_metadata_removed > 0 {
+        println!("   Metadata stripped: {} bytes", total_metadata_removed.to_string().green());
+    }
     println!("{}", "═══════════════════════════════════════".cyan());
 
-    return converted_urls;
+    converted_images
 }
 
+/// Delete the original of every converted image. Called once the page points to the WebP
+/// files, so a failure while rewriting the HTML never leaves it referencing deleted images.
+pub fn remove_converted_sources(converted_images: &[(String, String)]) {
+    for (source, _) in converted_images {
+        if let Err(e) = fs::remove_file(source) {
+            println!("  ⚠️  Failed to delete {}: {}", source, e);
+        }
+    }
+}
+
+fn convert_image(image_path: &str, metadata_options: &MetadataOptions, cache: Option<&ImageCache>) -> ConversionOutcome {
+    // Skip remote URLs and non-image files
+    if image_path.starts_with("http") {
+        return ConversionOutcome::Skipped(format!("⏭️  Skipping remote image: {}", image_path));
+    }
+    
+    // Check if file exists and is a supported format
+    let path = Path::new(image_path);
+    if !path.exists() {
+        return ConversionOutcome::Skipped(format!("⚠️  File not found: {}", image_path));
+    }
+    
+    let extension = path.extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or("")
+        .to_lowercase();
+    
+    // Skip if already WebP or unsupported format
+    if extension == "webp" {
+        return ConversionOutcome::Skipped(format!("⏭️  Already WebP: {}", image_path));
+    }
+    
+    if !["jpg", "jpeg", "png", "gif", "bmp", "tiff"].contains(&extension.as_str()) {
+        return ConversionOutcome::Skipped(format!("⏭️  Unsupported format: {}", image_path));
+    }
+    
+    let content = match fs::read(path) {
+        Ok(content) => content,
+        Err(e) => return ConversionOutcome::Failed(format!("❌ Failed to open {}: {}", image_path, e)),
+    };
+    
+    // Get original file size
+    let original_size = content.len() as u64;
+    
+    let cache_key = ImageCache::key(&content, &encoding_settings(metadata_options));
+    let cached = cache.and_then(|cache| cache.get(&cache_key));
+    let from_cache = cached.is_some();
+    
+    let encoded = match cached {
+        Some(encoded) => encoded,
+        None => match encode_image_file(&content, &extension, metadata_options) {
+            Ok(encoded) => {
+                if let Some(cache) = cache {
+                    // A cache failure only costs a re-encode on the next run
+                    let _ = cache.put(&cache_key, &encoded);
+                }
+                encoded
+            },
+            Err(e) => return ConversionOutcome::Failed(format!("❌ Failed to convert {}: {}", image_path, e)),
+        },
+    };
+    
+    // Create output path
+    let output_path = path.with_extension("webp");
+    
+    // Write WebP file
+    if let Err(e) = fs::write(&output_path, &encoded.webp_data) {
+        return ConversionOutcome::Failed(format!("❌ Failed to write {}: {}", output_path.display(), e));
+    }
+    
+    ConversionOutcome::Converted(ConvertedImage {
+        source: image_path.to_string(),
+        output_path: output_path.to_string_lossy().to_string(),
+        original_size,
+        new_size: encoded.webp_data.len() as u64,
+        label: encoded.label,
+        metadata_removed: metadata_size(&content).saturating_sub(encoded.metadata_kept),
+        video_candidate_frames: encoded.video_candidate_frames,
+        from_cache,
+    })
+}
+
+fn encode_image_file(content: &[u8], extension: &str, metadata_options: &MetadataOptions) -> Result<EncodedImage, String> {
+    // Animated GIFs keep all their frames, everything else is a still image
+    if extension == "gif" && let Some(animation) = encode_animated_gif(content)? {
+        let video_candidate_frames = if animation.is_video_candidate(content.len() as u64) {
+            Some(animation.frame_count)
+        } else {
+            None
+        };
+        let loops = if animation.loop_count == 0 {
+            "looping forever".to_string()
+        } else {
+            format!("played {} times", animation.loop_count)
+        };
+        return Ok(EncodedImage {
+            webp_data: animation.webp_data,
+            label: format!("animated, {} frames, {}", animation.frame_count, loops),
+            metadata_kept: 0,
+            video_candidate_frames,
+        });
+    }
+    
+    encode_still_image(content, metadata_options)
+}
+
+fn encode_still_image(content: &[u8], metadata_options: &MetadataOptions) -> Result<EncodedImage, String> {
+    let decoded = decode_image(content)?;
+    let (webp_data, mode) = encode_webp(&decoded.image)?;
+
+    let icc_profile = decoded.icc_profile.as_deref().filter(|_| metadata_options.keep_color_profile);
+    let exif = decoded.exif.as_deref()
+        .filter(|_| metadata_options.keep_copyright)
+        .and_then(copyright_exif);
+    let metadata_kept = icc_profile.map_or(0, |icc| icc.len()) + exif.as_ref().map_or(0, |exif| exif.len());
+
+    Ok(EncodedImage {
+        webp_data: add_webp_metadata(&webp_data, decoded.image.width(), decoded.image.height(), icc_profile, exif.as_deref()),
+        label: mode.label().to_string(),
+        metadata_kept,
+        video_candidate_frames: None,
+    })
+}
+
+/// GIFs above this size or frame count are better served as a video
+const VIDEO_CANDIDATE_BYTES: u64 = 500 * 1024;
+const VIDEO_CANDIDATE_FRAMES: usize = 50;
+/// Browsers play GIF frames shorter than this at 100ms
+const MIN_GIF_FRAME_DELAY_MS: u32 = 20;
+
+pub struct AnimatedWebp {
+    pub webp_data: Vec<u8>,
+    pub frame_count: usize,
+    /// WebP loop count, 0 meaning forever
+    pub loop_count: i32,
+}
+
+impl AnimatedWebp {
+    pub fn is_video_candidate(&self, original_size: u64) -> bool {
+        original_size >= VIDEO_CANDIDATE_BYTES || self.frame_count >= VIDEO_CANDIDATE_FRAMES
+    }
+}
+
+/// Convert an animated GIF to an animated WebP, keeping frame delays and loop count.
+/// Returns `None` for single-frame GIFs, which are converted as still images.
+pub fn encode_animated_gif(content: &[u8]) -> Result<Option<AnimatedWebp>, String> {
+    use image::AnimationDecoder;
+    use image::codecs::gif::GifDecoder;
+    use webp::{AnimEncoder, AnimFrame};
+
+    let decoder = GifDecoder::new(Cursor::new(content)).map_err(|e| format!("failed to decode: {}", e))?;
+    let frames = decoder.into_frames()
+        .collect_frames()
+        .map_err(|e| format!("failed to decode frames: {}", e))?;
+
+    if frames.len() < 2 {
+        return Ok(None);
+    }
+
+    let loop_count = read_gif_loop_count(content);
+    let (width, height) = frames[0].buffer().dimensions();
+
+    let mut config = WebPConfig::new().map_err(|_| "Failed to initialize WebP config".to_string())?;
+    config.quality = 80.0;
+
+    let mut encoder = AnimEncoder::new(width, height, &config);
+    encoder.set_loop_count(loop_count);
+
+    // Frames are placed on a timeline, each one starting when the previous one ends
+    let mut timestamp: i32 = 0;
+    for frame in &frames {
+        encoder.add_frame(AnimFrame::from_rgba(frame.buffer(), width, height, timestamp));
+
+        let (numerator, denominator) = frame.delay().numer_denom_ms();
+        let mut delay = numerator / denominator.max(1);
+        if delay < MIN_GIF_FRAME_DELAY_MS {
+            delay = 100;
+        }
+        timestamp += delay as i32;
+    }
+
+    let mut webp_data = encoder.try_encode()
+        .map_err(|e| format!("WebP animation encoding failed: {:?}", e))?
+        .to_vec();
+    set_last_frame_end(&mut webp_data, timestamp as u32);
+
+    Ok(Some(AnimatedWebp {
+        webp_data,
+        frame_count: frames.len(),
+        loop_count,
+    }))
+}
+
+/// Offsets of the `ANMF` frame payloads of an animated WebP
+fn animation_frames(webp: &[u8]) -> Vec<usize> {
+    let mut frames = Vec::new();
+    let mut i = 12;
+    while i + 8 <= webp.len() {
+        let size = u32::from_le_bytes([webp[i + 4], webp[i + 5], webp[i + 6], webp[i + 7]]) as usize;
+        if &webp[i..i + 4] == b"ANMF" && i + 8 + 16 <= webp.len() {
+            frames.push(i + 8);
+        }
+        i += 8 + size + size % 2;
+    }
+    frames
+}
+
+/// Duration of a frame, a 24-bit value after its offset and size
+fn frame_duration(webp: &[u8], frame: usize) -> u32 {
+    u32::from_le_bytes([webp[frame + 12], webp[frame + 13], webp[frame + 14], 0])
+}
+
+/// Make the last frame of an animated WebP last until `end_ms`. The webp crate closes the
+/// animation with a timestamp libwebp rejects, so the last frame gets the average duration
+/// instead of its own delay.
+fn set_last_frame_end(webp: &mut [u8], end_ms: u32) {
+    let frames = animation_frames(webp);
+    let Some((&last, previous)) = frames.split_last() else { return };
+
+    // Identical consecutive frames are merged by libwebp, so the start is read back from the file
+    let start_ms: u32 = previous.iter().map(|&frame| frame_duration(webp, frame)).sum();
+    let duration = end_ms.saturating_sub(start_ms).clamp(1, 0xFF_FFFF);
+    webp[last + 12..last + 15].copy_from_slice(&duration.to_le_bytes()[..3]);
+}
+
+/// Whether a GIF or WebP file holds more than one frame
+pub fn is_animated(content: &[u8]) -> bool {
+    if let Some(features) = webp::BitstreamFeatures::new(content) {
+        return features.has_animation();
+    }
+
+    let mut options = gif::DecodeOptions::new();
+    options.skip_frame_decoding(true);
+    let Ok(mut decoder) = options.read_info(Cursor::new(content)) else { return false };
+
+    let mut frame_count = 0;
+    while let Ok(Some(_)) = decoder.read_next_frame() {
+        frame_count += 1;
+        if frame_count > 1 {
+            return true;
+        }
+    }
+    false
+}
+
+/// Read the NETSCAPE loop extension of a GIF and translate it to a WebP loop count
+fn read_gif_loop_count(content: &[u8]) -> i32 {
+    let mut options = gif::DecodeOptions::new();
+    options.skip_frame_decoding(true);
+
+    let Ok(mut decoder) = options.read_info(Cursor::new(content)) else { return 0 };
+    // The loop extension may come after the first frame, read them all
+    while let Ok(Some(_)) = decoder.read_next_frame() {}
+
+    match decoder.repeat() {
+        gif::Repeat::Infinite => 0,
+        // GIF counts repetitions after the first play, WebP counts plays
+        gif::Repeat::Finite(repetitions) => repetitions as i32 + 1,
+    }
+}
+
+/// Widths (in px) of the resized variants generated for `srcset`
+pub const RESPONSIVE_WIDTHS: [u32; 4] = [480, 960, 1440, 1920];
+
+/// An image and the resized variants generated for it
+pub struct ResponsiveImage {
+    pub source: String,
+    pub source_width: u32,
+    /// (width, path) pairs, smallest first
+    pub variants: Vec<(u32, String)>,
+}
+
+pub fn generate_responsive_variants(images_urls: &[String]) -> Vec<ResponsiveImage> {
+    use image::imageops::FilterType;
+    use colored::*;
+
+    println!("\n{}", "📐 Generating responsive image variants...".cyan().bold());
+
+    let mut responsive_images: Vec<ResponsiveImage> = Vec::new();
+    let mut variants_count = 0;
+
+    for image_path in images_urls {
+        if image_path.starts_with("http") {
+            println!("  ⏭️  Skipping remote image: {}", image_path);
+            continue;
+        }
+
+        let path = Path::new(image_path);
+        let content = match fs::read(path) {
+            Ok(content) => content,
+            Err(e) => {
+                println!("  ❌ Failed to open {}: {}", image_path, e);
+                continue;
+            }
+        };
+        if is_animated(&content) {
+            println!("  ⏭️  Skipping animated image: {}", image_path);
+            continue;
+        }
+
+        let img = match decode_image(&content) {
+            Ok(decoded) => decoded.image,
+            Err(e) => {
+                println!("  ❌ Failed to decode {}: {}", image_path, e);
+                continue;
+            }
+        };
+
+        let (width, height) = (img.width(), img.height());
+        let file_stem = path.file_stem()
+            .and_then(|s| s.to_str())
+            .unwrap_or("image");
+
+        let mut variants: Vec<(u32, String)> = Vec::new();
+        for target_width in RESPONSIVE_WIDTHS {
+            // Never upscale
+            if target_width >= width {
+                break;
+            }
+
+            let target_height = ((height as f64 * target_width as f64 / width as f64).round() as u32).max(1);
+            let resized = img.resize_exact(target_width, target_height, FilterType::Lanczos3);
+
+            let webp_data = match encode_webp(&resized) {
+                Ok((data, _)) => data,
+                Err(e) => {
+                    println!("  ❌ Failed to encode {}px variant of {}: {}", target_width, image_path, e);
+                    continue;
+                }
+            };
+
+            let output_path = path.with_file_name(format!("{}-{}w.webp", file_stem, target_width));
+            match fs::write(&output_path, &webp_data) {
+                Ok(_) => {
+                    println!("  ✅ {} -> {} ({} bytes)", image_path, output_path.display(), webp_data.len());
+                    variants.push((target_width, output_path.to_string_lossy().to_string()));
+                },
+                Err(e) => {
+                    println!("  ❌ Failed to write {}: {}", output_path.display(), e);
+                }
+            }
+        }
+
+        if variants.is_empty() {
+            println!("  ⏭️  Already small enough ({}px wide): {}", width, image_path);
+            continue;
+        }
+
+        variants_count += variants.len();
+        responsive_images.push(ResponsiveImage {
+            source: image_path.clone(),
+            source_width: width,
+            variants,
+        });
+    }
+
+    println!("   Variants generated: {} for {} images", variants_count.to_string().green().bold(), responsive_images.len());
+
+    responsive_images
+}
+
+/// Encode an image to AVIF, dropping the alpha channel when the image is fully opaque
+pub fn encode_avif(img: &DynamicImage) -> Result<Vec<u8>, String> {
+    use image::codecs::avif::AvifEncoder;
+
+    let rgba = img.to_rgba8();
+    let flattened = if profile_image(&rgba).has_alpha {
+        DynamicImage::ImageRgba8(rgba)
+    } else {
+        DynamicImage::ImageRgb8(img.to_rgb8())
+    };
+
+    let mut avif_data: Vec<u8> = Vec::new();
+    // Speed 8 keeps the estimate fast, quality 70 is close to WebP quality 80
+    let encoder = AvifEncoder::new_with_speed_quality(&mut avif_data, 8, 70);
+    flattened.write_with_encoder(encoder)
+        .map_err(|e| format!("AVIF encoding failed: {}", e))?;
+    Ok(avif_data)
+}
+
+/// Download remote images and report what converting them to WebP/AVIF would save,
+/// without writing anything to disk.
+pub async fn estimate_remote_image_savings(images_urls: &[String], fetched: &FetchedResources) {
+    use crate::url_resolver::is_remote_url;
+    use colored::*;
+
+    println!("\n{}", "🔎 Estimating image optimization savings...".cyan().bold());
+
+    let mut estimated = 0;
+    let mut total_original: u64 = 0;
+    let mut total_webp_saved: i64 = 0;
+    let mut total_avif_saved: i64 = 0;
+
+    // An image used twice on the page is only estimated once
+    let mut seen: HashSet<&String> = HashSet::new();
+    for image_url in images_urls.iter().filter(|image_url| seen.insert(*image_url)) {
+        if !is_remote_url(image_url) {
+            continue;
+        }
+
+        let content = match fetched.get(image_url).await {
+            Ok(content) => content,
+            Err(e) => {
+                println!("  ❌ Failed to download {}: {}", image_url, e);
+                continue;
+            }
+        };
+
+        let img = match decode_image(&content) {
+            Ok(decoded) => decoded.image,
+            Err(e) => {
+                println!("  ⏭️  Cannot decode {}: {}", image_url, e);
+                continue;
+            }
+        };
+
+        let original_size = content.len() as i64;
+        let animation = match image::guess_format(&content) {
+            Ok(image::ImageFormat::Gif) => encode_animated_gif(&content),
+            _ => Ok(None),
+        };
+        let (webp_size, avif_size) = match animation {
+            // Animated GIFs become animated WebPs, AVIF sequences are not supported by the encoder
+            Ok(Some(animation)) => (
+                Ok(animation.webp_data.len() as i64),
+                Err("not supported for animations".to_string()),
+            ),
+            _ => (
+                encode_webp(&img).map(|(data, _)| data.len() as i64),
+                encode_avif(&img).map(|data| data.len() as i64),
+            ),
+        };
+
+        let format_saving = |size: &Result<i64, String>| match size {
+            Ok(size) if *size < original_size => format!("{} bytes, saves {}", size, original_size - size).green(),
+            Ok(size) => format!("{} bytes, no gain", size).yellow(),
+            Err(e) => e.clone().red(),
+        };
+
+        println!("  🖼️  {} ({} bytes)", image_url, original_size);
+        println!("      WebP: {}", format_saving(&webp_size));
+        println!("      AVIF: {}", format_saving(&avif_size));
+
+        // Keep the original when a format would make it bigger
+        estimated += 1;
+        total_original += original_size as u64;
+        total_webp_saved += webp_size.map(|size| (original_size - size).max(0)).unwrap_or(0);
+        total_avif_saved += avif_size.map(|size| (original_size - size).max(0)).unwrap_or(0);
+    }
+
+    println!("\n{}", "═══════════════════════════════════════".cyan());
+    println!("📊 Estimation summary:");
+    println!("   Images analyzed: {} ({} bytes)", estimated.to_string().green().bold(), total_original);
+    println!("   Potential savings with WebP: {} bytes ({:.2} KB)",
+        total_webp_saved.to_string().green().bold(),
+        total_webp_saved as f64 / 1024.0);
+    println!("   Potential savings with AVIF: {} bytes ({:.2} KB)",
+        total_avif_saved.to_string().green().bold(),
+        total_avif_saved as f64 / 1024.0);
+    println!("{}", "═══════════════════════════════════════".cyan());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A 2x2 GIF of plain frames, given as (palette index, delay in hundredths of a second)
+    fn animated_gif(frames: &[(u8, u16)]) -> Vec<u8> {
+        let mut content = Vec::new();
+        let palette = [255, 0, 0, 0, 255, 0, 0, 0, 255];
+        let mut encoder = gif::Encoder::new(&mut content, 2, 2, &palette).unwrap();
+        for &(color, delay) in frames {
+            let mut frame = gif::Frame::from_indexed_pixels(2, 2, vec![color; 4], None);
+            frame.delay = delay;
+            encoder.write_frame(&frame).unwrap();
+        }
+        drop(encoder);
+        content
+    }
+
+    fn frame_durations(webp: &[u8]) -> Vec<u32> {
+        animation_frames(webp).into_iter().map(|frame| frame_duration(webp, frame)).collect()
+    }
+
+    #[test]
+    fn animated_webp_keeps_the_delay_of_the_last_frame() {
+        let animation = encode_animated_gif(&animated_gif(&[(0, 10), (1, 20), (2, 50)])).unwrap().unwrap();
+        assert_eq!(frame_durations(&animation.webp_data), vec![100, 200, 500]);
+    }
+
+    #[test]
+    fn animated_webp_keeps_the_duration_of_merged_last_frames() {
+        // The last two frames are identical and become one frame
+        let animation = encode_animated_gif(&animated_gif(&[(0, 10), (1, 10), (1, 20)])).unwrap().unwrap();
+        assert_eq!(frame_durations(&animation.webp_data), vec![100, 300]);
+    }
+}
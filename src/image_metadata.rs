@@ -0,0 +1,204 @@
+/// EXIF tags kept when copyright metadata is preserved
+const TAG_ARTIST: u16 = 0x013B;
+const TAG_COPYRIGHT: u16 = 0x8298;
+/// EXIF field type of NUL-terminated strings
+const TYPE_ASCII: u16 = 2;
+
+/// Which metadata survives the conversion, everything else is stripped
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MetadataOptions {
+    pub keep_copyright: bool,
+    pub keep_color_profile: bool,
+}
+
+/// Bytes taken by metadata (EXIF, XMP, ICC, IPTC, comments, text) in a JPEG or PNG file
+pub fn metadata_size(content: &[u8]) -> usize {
+    if content.starts_with(&[0xFF, 0xD8]) {
+        jpeg_metadata_size(content)
+    } else if content.starts_with(b"\x89PNG\r\n\x1a\n") {
+        png_metadata_size(content)
+    } else {
+        0
+    }
+}
+
+fn jpeg_metadata_size(content: &[u8]) -> usize {
+    let mut size = 0;
+    let mut i = 2;
+
+    while i + 4 <= content.len() && content[i] == 0xFF {
+        let marker = content[i + 1];
+        // Only entropy-coded data follows the start of scan
+        if marker == 0xDA {
+            break;
+        }
+        // Fill byte before the actual marker
+        if marker == 0xFF {
+            i += 1;
+            continue;
+        }
+
+        let length = u16::from_be_bytes([content[i + 2], content[i + 3]]) as usize;
+        // APP1-APP15 hold EXIF, XMP, ICC, IPTC... APP0 (JFIF) is kept by every encoder
+        if (0xE1..=0xEF).contains(&marker) || marker == 0xFE {
+            size += length + 2;
+        }
+        i += length + 2;
+    }
+
+    size
+}
+
+fn png_metadata_size(content: &[u8]) -> usize {
+    let metadata_chunks: [&[u8]; 6] = [b"eXIf", b"iCCP", b"tEXt", b"zTXt", b"iTXt", b"tIME"];
+    let mut size = 0;
+    let mut i = 8;
+
+    while i + 8 <= content.len() {
+        let length = u32::from_be_bytes([content[i], content[i + 1], content[i + 2], content[i + 3]]) as usize;
+        let chunk_type = &content[i + 4..i + 8];
+        if metadata_chunks.contains(&chunk_type) {
+            // Length, type and CRC come on top of the data
+            size += length + 12;
+        }
+        if chunk_type == b"IEND" {
+            break;
+        }
+        i += length + 12;
+    }
+
+    size
+}
+
+/// Build a minimal EXIF block holding only the Artist and Copyright tags of `exif`
+pub fn copyright_exif(exif: &[u8]) -> Option<Vec<u8>> {
+    let little_endian = match exif.get(0..4)? {
+        [0x49, 0x49, 42, 0] => true,
+        [0x4D, 0x4D, 0, 42] => false,
+        _ => return None,
+    };
+    let read_u16 = |offset: usize| -> Option<u16> {
+        let bytes = [*exif.get(offset)?, *exif.get(offset + 1)?];
+        Some(if little_endian { u16::from_le_bytes(bytes) } else { u16::from_be_bytes(bytes) })
+    };
+    let read_u32 = |offset: usize| -> Option<u32> {
+        let bytes = [*exif.get(offset)?, *exif.get(offset + 1)?, *exif.get(offset + 2)?, *exif.get(offset + 3)?];
+        Some(if little_endian { u32::from_le_bytes(bytes) } else { u32::from_be_bytes(bytes) })
+    };
+
+    // Artist and Copyright live in the first IFD
+    let ifd_offset = read_u32(4)? as usize;
+    let entry_count = read_u16(ifd_offset)? as usize;
+
+    let mut fields: Vec<(u16, Vec<u8>)> = Vec::new();
+    for index in 0..entry_count {
+        let entry = ifd_offset + 2 + index * 12;
+        let tag = read_u16(entry)?;
+        if (tag != TAG_ARTIST && tag != TAG_COPYRIGHT) || read_u16(entry + 2)? != TYPE_ASCII {
+            continue;
+        }
+
+        let count = read_u32(entry + 4)? as usize;
+        let value_offset = if count <= 4 { entry + 8 } else { read_u32(entry + 8)? as usize };
+        fields.push((tag, exif.get(value_offset..value_offset + count)?.to_vec()));
+    }
+
+    if fields.is_empty() {
+        return None;
+    }
+
+    // Little-endian TIFF header, one IFD, then the string values
+    let mut output: Vec<u8> = vec![0x49, 0x49, 42, 0, 8, 0, 0, 0];
+    let values_offset = 8 + 2 + fields.len() * 12 + 4;
+    let mut values: Vec<u8> = Vec::new();
+
+    output.extend((fields.len() as u16).to_le_bytes());
+    for (tag, value) in &fields {
+        output.extend(tag.to_le_bytes());
+        output.extend(TYPE_ASCII.to_le_bytes());
+        output.extend((value.len() as u32).to_le_bytes());
+        if value.len() <= 4 {
+            let mut inline_value = value.clone();
+            inline_value.resize(4, 0);
+            output.extend(inline_value);
+        } else {
+            output.extend(((values_offset + values.len()) as u32).to_le_bytes());
+            values.extend(value);
+            // Keep values word-aligned
+            if values.len() % 2 == 1 {
+                values.push(0);
+            }
+        }
+    }
+    output.extend(0u32.to_le_bytes());
+    output.extend(values);
+
+    Some(output)
+}
+
+/// Rewrite a WebP file in the extended format to embed an ICC profile and/or EXIF block
+pub fn add_webp_metadata(webp: &[u8], width: u32, height: u32, icc_profile: Option<&[u8]>, exif: Option<&[u8]>) -> Vec<u8> {
+    if icc_profile.is_none() && exif.is_none() {
+        return webp.to_vec();
+    }
+
+    // Split the RIFF container into its chunks, the VP8X header is rebuilt below
+    let mut chunks: Vec<(&[u8], &[u8])> = Vec::new();
+    let mut i = 12;
+    while i + 8 <= webp.len() {
+        let fourcc = &webp[i..i + 4];
+        let size = u32::from_le_bytes([webp[i + 4], webp[i + 5], webp[i + 6], webp[i + 7]]) as usize;
+        let Some(data) = webp.get(i + 8..i + 8 + size) else { break };
+        if fourcc != b"VP8X" {
+            chunks.push((fourcc, data));
+        }
+        i += 8 + size + size % 2;
+    }
+
+    let has_alpha = webp::BitstreamFeatures::new(webp)
+        .map(|features| features.has_alpha())
+        .unwrap_or(false);
+
+    let mut flags = 0u8;
+    if icc_profile.is_some() {
+        flags |= 0x20;
+    }
+    if has_alpha {
+        flags |= 0x10;
+    }
+    if exif.is_some() {
+        flags |= 0x08;
+    }
+
+    let mut vp8x = vec![flags, 0, 0, 0];
+    vp8x.extend(&(width - 1).to_le_bytes()[..3]);
+    vp8x.extend(&(height - 1).to_le_bytes()[..3]);
+
+    // Chunk order is mandated: VP8X, ICCP, image data, EXIF
+    let mut body = b"WEBP".to_vec();
+    write_riff_chunk(&mut body, b"VP8X", &vp8x);
+    if let Some(icc_profile) = icc_profile {
+        write_riff_chunk(&mut body, b"ICCP", icc_profile);
+    }
+    for (fourcc, data) in chunks {
+        write_riff_chunk(&mut body, fourcc, data);
+    }
+    if let Some(exif) = exif {
+        write_riff_chunk(&mut body, b"EXIF", exif);
+    }
+
+    let mut output = b"RIFF".to_vec();
+    output.extend((body.len() as u32).to_le_bytes());
+    output.extend(body);
+    output
+}
+
+fn write_riff_chunk(output: &mut Vec<u8>, fourcc: &[u8], data: &[u8]) {
+    output.extend(fourcc);
+    output.extend((data.len() as u32).to_le_bytes());
+    output.extend(data);
+    // Chunks are padded to an even size
+    if data.len() % 2 == 1 {
+        output.push(0);
+    }
+}
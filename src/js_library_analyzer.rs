@@ -0,0 +1,236 @@
+use regex::Regex;
+use std::collections::BTreeSet;
+use crate::resource_extractor::FetchedResources;
+
+/// How to recognize a library from its code or its URL
+struct LibrarySignature {
+    name: &'static str,
+    /// Matched against the code, the license banner kept by minifiers. Anchored at the start
+    /// of a banner line, so plugins mentioning the library in their own banner do not match.
+    content_pattern: &'static str,
+    /// Captures the version from the code
+    version_pattern: Option<&'static str>,
+    /// File and package names the library is published under
+    url_names: &'static [&'static str],
+    alternative: &'static str,
+}
+
+/// Libraries more specific than others come first, jQuery UI before jQuery
+const LIBRARIES: [LibrarySignature; 12] = [
+    LibrarySignature {
+        name: "jQuery UI",
+        content_pattern: r"(?m)^\s*(?:/\*+!?|\*)\s*jQuery UI - v\d",
+        version_pattern: Some(r"(?m)^\s*(?:/\*+!?|\*)\s*jQuery UI - v(\d+\.\d+\.\d+)"),
+        url_names: &["jquery-ui"],
+        alternative: "native <dialog>, <details> and <input type=\"date\"> elements",
+    },
+    LibrarySignature {
+        name: "jQuery",
+        content_pattern: r"(?m)^\s*(?:/\*+!?|\*)\s*jQuery (?:JavaScript Library )?v\d",
+        version_pattern: Some(r"(?m)^\s*(?:/\*+!?|\*)\s*jQuery (?:JavaScript Library )?v(\d+\.\d+\.\d+)"),
+        url_names: &["jquery"],
+        alternative: "native DOM APIs (querySelector, classList, fetch), or Cash (6 KB)",
+    },
+    LibrarySignature {
+        name: "Lodash",
+        content_pattern: r"Lodash <https://lodash\.com/>|lodash\.com/license",
+        version_pattern: Some(r"VERSION\s*=\s*['\x22](\d+\.\d+\.\d+)"),
+        url_names: &["lodash"],
+        alternative: "native array and object methods, or per-method imports from lodash-es",
+    },
+    LibrarySignature {
+        name: "Underscore",
+        content_pattern: r"(?m)^\s*//\s*Underscore\.js \d",
+        version_pattern: Some(r"Underscore\.js (\d+\.\d+\.\d+)"),
+        url_names: &["underscore"],
+        alternative: "native array and object methods",
+    },
+    LibrarySignature {
+        name: "Moment.js",
+        // Locale files start with `//! moment.js locale configuration` instead
+        content_pattern: r"(?m)^//! moment\.js[ \t]*\r?\n//! version",
+        version_pattern: Some(r"//! version : (\d+\.\d+\.\d+)"),
+        url_names: &["moment", "moment-with-locales"],
+        alternative: "Day.js (2 KB), date-fns or the native Intl.DateTimeFormat",
+    },
+    LibrarySignature {
+        name: "Bootstrap",
+        content_pattern: r"(?m)^\s*(?:/\*+!?|\*)\s*Bootstrap v\d",
+        version_pattern: Some(r"(?m)^\s*(?:/\*+!?|\*)\s*Bootstrap v(\d+\.\d+\.\d+)"),
+        url_names: &["bootstrap", "bootstrap.bundle"],
+        alternative: "the plugins actually used (bootstrap/js/dist/*), Bootstrap 5 no longer needs jQuery",
+    },
+    LibrarySignature {
+        name: "Popper",
+        content_pattern: r"(?m)^\s*(?:/\*+!?|\*)\s*(?:@popperjs/core v\d|@fileOverview Kickass library to create and place poppers)",
+        version_pattern: Some(r"(?m)^\s*(?:/\*+!?|\*)\s*@popperjs/core v(\d+\.\d+\.\d+)"),
+        url_names: &["popper"],
+        alternative: "Floating UI, or the CSS anchor positioning",
+    },
+    LibrarySignature {
+        name: "AngularJS",
+        content_pattern: r"(?m)^\s*(?:/\*+!?|\*)?\s*(?:@license )?AngularJS v\d",
+        version_pattern: Some(r"(?m)^\s*(?:/\*+!?|\*)?\s*(?:@license )?AngularJS v(\d+\.\d+\.\d+)"),
+        url_names: &["angular"],
+        alternative: "a maintained framework, AngularJS is no longer supported",
+    },
+    LibrarySignature {
+        name: "Vue",
+        content_pattern: r"(?m)^\s*(?:/\*+!?|\*)\s*Vue\.js v\d",
+        version_pattern: Some(r"(?m)^\s*(?:/\*+!?|\*)\s*Vue\.js v(\d+\.\d+\.\d+)"),
+        url_names: &["vue", "vue.global"],
+        alternative: "Petite Vue (6 KB) for pages only sprinkled with interactivity",
+    },
+    LibrarySignature {
+        name: "Modernizr",
+        content_pattern: r"(?mi)^\s*/\*! modernizr \d",
+        version_pattern: Some(r"(?mi)^\s*/\*! modernizr (\d+\.\d+\.\d+)"),
+        url_names: &["modernizr"],
+        alternative: "CSS @supports and native feature checks",
+    },
+    LibrarySignature {
+        name: "Chart.js",
+        content_pattern: r"(?m)^\s*(?:/\*+!?|\*)\s*Chart\.js v\d",
+        version_pattern: Some(r"(?m)^\s*(?:/\*+!?|\*)\s*Chart\.js v(\d+\.\d+\.\d+)"),
+        url_names: &["chart", "chart.umd"],
+        alternative: "Chart.js 3+ with tree-shaking, or a static SVG for charts that do not change",
+    },
+    LibrarySignature {
+        name: "Swiper",
+        content_pattern: r"(?m)^\s*(?:/\*+!?|\*)\s*Swiper \d+\.\d+\.\d+",
+        version_pattern: Some(r"(?m)^\s*(?:/\*+!?|\*)\s*Swiper (\d+\.\d+\.\d+)"),
+        url_names: &["swiper", "swiper-bundle"],
+        alternative: "CSS scroll snapping (scroll-snap-type)",
+    },
+];
+
+/// A library found in a script of the page
+pub struct DetectedLibrary {
+    pub name: String,
+    pub version: Option<String>,
+    pub script_url: String,
+    /// Size of the whole script, bundles may hold several libraries
+    pub script_size: usize,
+}
+
+/// A library loaded by several scripts
+pub struct DuplicateLibrary {
+    pub name: String,
+    /// Distinct versions, several when the page loads different versions
+    pub versions: Vec<String>,
+    pub scripts: Vec<String>,
+    /// Bytes of the scripts beyond the biggest one
+    pub wasted_bytes: usize,
+}
+
+pub struct LibraryAnalysis {
+    pub libraries: Vec<DetectedLibrary>,
+    pub duplicates: Vec<DuplicateLibrary>,
+    /// (library, lighter alternative), once per library
+    pub alternatives: Vec<(String, String)>,
+}
+
+/// Find the known libraries of the code, with their version when it can be read
+fn detect_in_content(content: &str) -> Vec<(&'static LibrarySignature, Option<String>)> {
+    LIBRARIES.iter()
+        .filter(|library| Regex::new(library.content_pattern).unwrap().is_match(content))
+        .map(|library| {
+            let version = library.version_pattern
+                .and_then(|pattern| Regex::new(pattern).unwrap().captures(content))
+                .and_then(|cap| cap.iter().skip(1).flatten().next().map(|m| m.as_str().to_string()));
+            (library, version)
+        })
+        .collect()
+}
+
+/// Where scripts named after their package come from: public CDNs and installed packages
+const PACKAGE_LOCATIONS: [&str; 8] = [
+    "cdnjs.cloudflare.com/", "cdn.jsdelivr.net/", "unpkg.com/", "code.jquery.com/",
+    "ajax.googleapis.com/", "bootstrapcdn.com/", "ajax.aspnetcdn.com/", "/node_modules/",
+];
+
+/// Find the library a script URL is named after, such as `jquery-3.6.0.min.js`,
+/// `ajax/libs/jquery/3.6.0/jquery.min.js` or `npm/jquery@3.6.0/dist/jquery.min.js`.
+/// A bare name like `chart.js` is only trusted with a version or from a CDN, pages often
+/// name their own scripts after what they do.
+fn detect_in_url(url: &str) -> Option<(&'static LibrarySignature, Option<String>)> {
+    let path = url.split(['?', '#']).next().unwrap_or("").to_lowercase();
+    let file_name = path.rsplit('/').next().unwrap_or("");
+    let stem = file_name.trim_end_matches(".js").trim_end_matches(".min");
+    let version_re = Regex::new(r"^[-.@/]?v?(\d+\.\d+\.\d+)").unwrap();
+    let from_package = PACKAGE_LOCATIONS.iter().any(|location| path.contains(location));
+
+    LIBRARIES.iter()
+        .find_map(|library| {
+            // The version follows the package name, in the file name or in a directory
+            let version = library.url_names.iter()
+                .flat_map(|name| path.match_indices(*name).map(|(index, _)| index + name.len()))
+                .find_map(|end| version_re.captures(&path[end..]))
+                .map(|cap| cap[1].to_string());
+            library.url_names.iter().find(|name| {
+                let versioned_stem = stem.strip_prefix(**name).is_some_and(|rest| version_re.is_match(rest));
+                versioned_stem || (stem == **name && (version.is_some() || from_package))
+            })?;
+            Some((library, version))
+        })
+}
+
+/// Fingerprint the libraries of the scripts loaded by the page, and flag the ones loaded twice.
+/// `script_urls` are the resolved `src` of the page scripts, read from `fetched`.
+pub async fn analyze_libraries(script_urls: &[String], fetched: &FetchedResources) -> LibraryAnalysis {
+    let mut libraries: Vec<DetectedLibrary> = Vec::new();
+
+    for script_url in script_urls {
+        let (mut detected, script_size) = match fetched.get(script_url).await {
+            Ok(content) => (detect_in_content(&String::from_utf8_lossy(&content)), content.len()),
+            Err(_) => (Vec::new(), 0),
+        };
+
+        // The URL names the library when its banner was stripped, and often its version
+        if let Some((library, url_version)) = detect_in_url(script_url) {
+            match detected.iter_mut().find(|(detected_library, _)| detected_library.name == library.name) {
+                Some((_, version)) if version.is_none() => *version = url_version,
+                Some(_) => {},
+                None => detected.push((library, url_version)),
+            }
+        }
+
+        for (library, version) in detected {
+            libraries.push(DetectedLibrary {
+                name: library.name.to_string(),
+                version,
+                script_url: script_url.clone(),
+                script_size,
+            });
+        }
+    }
+
+    let mut duplicates = Vec::new();
+    let mut alternatives = Vec::new();
+    for library in &LIBRARIES {
+        let loaded: Vec<&DetectedLibrary> = libraries.iter().filter(|detected| detected.name == library.name).collect();
+        if loaded.is_empty() {
+            continue;
+        }
+        alternatives.push((library.name.to_string(), library.alternative.to_string()));
+        if loaded.len() < 2 {
+            continue;
+        }
+
+        let versions: BTreeSet<String> = loaded.iter().filter_map(|detected| detected.version.clone()).collect();
+        let total_bytes: usize = loaded.iter().map(|detected| detected.script_size).sum();
+        let biggest = loaded.iter().map(|detected| detected.script_size).max().unwrap_or(0);
+        duplicates.push(DuplicateLibrary {
+            name: library.name.to_string(),
+            versions: versions.into_iter().collect(),
+            scripts: loaded.iter().map(|detected| detected.script_url.clone()).collect(),
+            wasted_bytes: total_bytes - biggest,
+        });
+    }
+
+    LibraryAnalysis {
+        libraries,
+        duplicates,
+        alternatives,
+    }
+}
@@ -0,0 +1,224 @@
+use flate2::Compression;
+use flate2::write::GzEncoder;
+use oxc_allocator::Allocator;
+use oxc_codegen::{Codegen, CodegenOptions, CommentOptions, LegalComment};
+use oxc_minifier::{CompressOptions, MangleOptions, Minifier, MinifierOptions};
+use oxc_parser::Parser;
+use oxc_span::SourceType;
+use regex::Regex;
+use scraper::{Html, Selector};
+use std::fs;
+use std::io::Write;
+use crate::html_manager::get_tag_attribute;
+use crate::output::print_savings;
+use crate::resource_extractor::FetchedResources;
+use crate::url_resolver::{is_remote_url, resolve_url};
+
+/// Size of a script of the page, as served and once minified
+pub struct ScriptSize {
+    pub name: String,
+    pub size: usize,
+    /// Minified size, `None` when the script could not be parsed
+    pub minified_size: Option<usize>,
+    pub gzipped_size: usize,
+    pub error: Option<String>,
+}
+
+pub struct ScriptAnalysis {
+    /// Biggest scripts first
+    pub scripts: Vec<ScriptSize>,
+    pub total_bytes: usize,
+    pub total_minified_bytes: usize,
+    pub total_gzipped_bytes: usize,
+}
+
+/// Minify a script: compression, mangling of the local names and whitespace.
+/// `module` scripts are parsed as ES modules.
+pub fn minify_js(code: &str, module: bool) -> Result<String, String> {
+    let allocator = Allocator::default();
+    // Classic scripts are sloppy-mode scripts whose top-level names are globals shared with the page
+    let source_type = SourceType::mjs().with_script(!module);
+
+    let parsed = Parser::new(&allocator, code, source_type).parse();
+    if let Some(error) = parsed.errors.first() {
+        return Err(format!("failed to parse JavaScript: {}", error));
+    }
+    let mut program = parsed.program;
+
+    // The safest compression keeps the code relying on getters, `Function.length` and the like working
+    let minified = Minifier::new(MinifierOptions {
+        mangle: Some(MangleOptions::default()),
+        compress: Some(CompressOptions::safest()),
+    }).minify(&allocator, &mut program);
+
+    // License comments (`/*! ... */`, `@license`) are kept
+    let codegen_options = CodegenOptions {
+        comments: CommentOptions { legal: LegalComment::Inline, ..CommentOptions::disabled() },
+        ..CodegenOptions::minify()
+    };
+    let output = Codegen::new()
+        .with_options(codegen_options)
+        .with_scoping(minified.scoping)
+        .build(&program);
+
+    Ok(output.code)
+}
+
+/// Size of `content` once compressed with gzip, as most servers send it
+pub fn gzip_size(content: &[u8]) -> usize {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(content).expect("Failed to compress in memory");
+    encoder.finish().map(|compressed| compressed.len()).unwrap_or(content.len())
+}
+
+/// Whether a `<script>` of this `type` holds JavaScript, and not JSON-LD, a template or another data block
+fn is_javascript(script_type: Option<&str>) -> bool {
+    match script_type.map(|t| t.trim().to_lowercase()) {
+        None => true,
+        Some(script_type) => matches!(script_type.as_str(),
+            "" | "module" | "text/javascript" | "application/javascript" | "text/ecmascript" | "application/ecmascript"),
+    }
+}
+
+fn is_module(script_type: Option<&str>) -> bool {
+    script_type.is_some_and(|t| t.trim().eq_ignore_ascii_case("module"))
+}
+
+/// Measure the scripts of the page, external and inline, as served, minified and gzipped
+pub async fn analyze_scripts(document: &Html, base_url: &str, fetched: &FetchedResources) -> ScriptAnalysis {
+    let script_selector = Selector::parse("script").unwrap();
+    let mut scripts: Vec<ScriptSize> = Vec::new();
+    let mut inline_count = 0;
+
+    for script in document.select(&script_selector) {
+        if !is_javascript(script.value().attr("type")) {
+            continue;
+        }
+
+        let (name, content) = match script.value().attr("src") {
+            Some(src) => {
+                let script_url = resolve_url(base_url, src);
+                match fetched.get(&script_url).await {
+                    Ok(content) => (script_url, String::from_utf8_lossy(&content).to_string()),
+                    Err(e) => {
+                        scripts.push(ScriptSize {
+                            name: script_url,
+                            size: 0,
+                            minified_size: None,
+                            gzipped_size: 0,
+                            error: Some(format!("failed to fetch: {}", e)),
+                        });
+                        continue;
+                    }
+                }
+            },
+            None => {
+                let content = script.text().collect::<String>();
+                if content.trim().is_empty() {
+                    continue;
+                }
+                inline_count += 1;
+                (format!("<script> #{}", inline_count), content)
+            },
+        };
+
+        let (minified_size, gzipped_size, error) = match minify_js(&content, is_module(script.value().attr("type"))) {
+            Ok(minified) => (Some(minified.len()), gzip_size(minified.as_bytes()), None),
+            Err(e) => (None, gzip_size(content.as_bytes()), Some(e)),
+        };
+
+        scripts.push(ScriptSize {
+            name,
+            size: content.len(),
+            minified_size,
+            gzipped_size,
+            error,
+        });
+    }
+
+    scripts.sort_by_key(|script| std::cmp::Reverse(script.size));
+
+    ScriptAnalysis {
+        total_bytes: scripts.iter().map(|script| script.size).sum(),
+        total_minified_bytes: scripts.iter().map(|script| script.minified_size.unwrap_or(script.size)).sum(),
+        total_gzipped_bytes: scripts.iter().map(|script| script.gzipped_size).sum(),
+        scripts,
+    }
+}
+
+/// Minify the local scripts used by the page in place, and its inline `<script>` blocks
+pub fn minify_scripts(html: &str, file_path: &str) {
+    let document = Html::parse_document(html);
+    let script_selector = Selector::parse("script[src]").unwrap();
+
+    for script in document.select(&script_selector) {
+        let script_type = script.value().attr("type");
+        if !is_javascript(script_type) {
+            continue;
+        }
+        let Some(src) = script.value().attr("src") else { continue };
+
+        let script_path = resolve_url(file_path, src);
+        if is_remote_url(&script_path) {
+            println!("⏭️  Skipping remote script: {}", script_path);
+            continue;
+        }
+        // Already minified bundles are left alone
+        if script_path.ends_with(".min.js") {
+            continue;
+        }
+
+        let content = match fs::read_to_string(&script_path) {
+            Ok(content) => content,
+            Err(e) => {
+                println!("❌ Failed to read {}: {}", script_path, e);
+                continue;
+            }
+        };
+        match minify_js(&content, is_module(script_type)) {
+            Ok(minified) if minified.len() < content.len() => {
+                fs::write(&script_path, &minified)
+                    .unwrap_or_else(|_| panic!("Failed to write to file: {}", script_path));
+                print_savings(&format!("Minified file saved as {}", script_path), content.len(), minified.len());
+            },
+            Ok(_) => {},
+            Err(e) => println!("❌ Failed to minify {}: {}", script_path, e),
+        }
+    }
+
+    // Inline scripts are replaced by their byte range, the same code may appear elsewhere in the page
+    let script_re = Regex::new(r"(?is)<script\b([^>]*)>(.*?)</script\s*>").unwrap();
+    let mut modified_html = String::new();
+    let mut copied_up_to = 0;
+    let mut inline_updated = 0;
+
+    for cap in script_re.captures_iter(html) {
+        let tag = format!("<script{}>", &cap[1]);
+        let script_type = get_tag_attribute(&tag, "type");
+        if get_tag_attribute(&tag, "src").is_some() || !is_javascript(script_type.as_deref()) {
+            continue;
+        }
+        let content = cap.get(2).unwrap();
+        if content.as_str().trim().is_empty() {
+            continue;
+        }
+
+        match minify_js(content.as_str(), is_module(script_type.as_deref())) {
+            Ok(minified) if minified.len() < content.len() => {
+                inline_updated += 1;
+                print_savings(&format!("Minified <script> #{} in {}", inline_updated, file_path), content.len(), minified.len());
+                modified_html.push_str(&html[copied_up_to..content.start()]);
+                modified_html.push_str(&minified);
+                copied_up_to = content.end();
+            },
+            Ok(_) => {},
+            Err(e) => println!("❌ Failed to minify a <script> block of {}: {}", file_path, e),
+        }
+    }
+
+    if inline_updated > 0 {
+        modified_html.push_str(&html[copied_up_to..]);
+        fs::write(file_path, &modified_html)
+            .unwrap_or_else(|_| panic!("Failed to write to file: {}", file_path));
+    }
+}
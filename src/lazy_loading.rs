@@ -0,0 +1,118 @@
+use scraper::{Html, Selector};
+use std::collections::HashMap;
+use crate::critical_css::above_the_fold_elements;
+use crate::html_manager::add_loading_attributes;
+use crate::image_analyzer::get_intrinsic_dimensions;
+use crate::resource_extractor::FetchedResources;
+use crate::url_resolver::resolve_url;
+
+/// Smallest area, in CSS pixels, for an image to be taken as the hero image. Logos and icons
+/// above the fold are smaller.
+const MIN_HERO_AREA: u64 = 200 * 200;
+
+/// Loading attributes decided for an `<img>` or `<iframe>` of the page
+pub struct LoadingHint {
+    pub tag_name: &'static str,
+    /// Resolved `src` of the element
+    pub url: String,
+    /// Index among the elements with the same tag and URL, in document order
+    pub occurrence: usize,
+    /// Below the fold: gets `loading="lazy"` and `decoding="async"`
+    pub lazy: bool,
+    /// Likely LCP image: gets `fetchpriority="high"`
+    pub hero: bool,
+}
+
+pub struct LazyLoadingPlan {
+    pub hints: Vec<LoadingHint>,
+    /// Bytes of the lazy-loaded elements, no longer downloaded before the page is displayed
+    pub deferred_bytes: usize,
+}
+
+/// Decide which images and iframes are lazy-loaded: the ones estimated below the fold, except
+/// the first `eager_images` images and the hero image, the biggest image above the fold.
+/// Elements with a `loading` attribute are left as their author wrote them.
+pub async fn plan_lazy_loading(document: &Html, base_url: &str, viewport: (u32, u32), eager_images: usize, fetched: &FetchedResources) -> LazyLoadingPlan {
+    let above_the_fold = above_the_fold_elements(document, viewport);
+    let element_selector = Selector::parse("img[src], iframe[src]").unwrap();
+
+    let mut hints: Vec<LoadingHint> = Vec::new();
+    let mut sizes: Vec<usize> = Vec::new();
+    let mut occurrences: HashMap<(&str, String), usize> = HashMap::new();
+    let mut image_count = 0;
+    // (index in hints, area) of the biggest image above the fold
+    let mut hero: Option<(usize, u64)> = None;
+
+    for element in document.select(&element_selector) {
+        let value = element.value();
+        let tag_name = if value.name() == "img" { "img" } else { "iframe" };
+        let Some(src) = value.attr("src") else { continue };
+        if src.starts_with("data:") {
+            continue;
+        }
+        let url = resolve_url(base_url, src);
+
+        let occurrence = occurrences.entry((tag_name, url.clone())).or_insert(0);
+        let index = hints.len();
+        let content = fetched.get(&url).await.ok();
+        sizes.push(content.as_ref().map(|content| content.len()).unwrap_or(0));
+
+        let is_above_the_fold = above_the_fold.contains(&element.id());
+        let mut is_eager = is_above_the_fold || value.attr("loading").is_some();
+        if tag_name == "img" {
+            image_count += 1;
+            is_eager |= image_count <= eager_images;
+
+            if is_above_the_fold {
+                let declared = |name: &str| value.attr(name).and_then(|v| v.trim_end_matches("px").parse::<u64>().ok());
+                let area = match (declared("width"), declared("height")) {
+                    (Some(width), Some(height)) => Some(width * height),
+                    _ => content.as_deref()
+                        .and_then(get_intrinsic_dimensions)
+                        .map(|(width, height)| width as u64 * height as u64),
+                };
+                if let Some(area) = area && area >= MIN_HERO_AREA && hero.is_none_or(|(_, biggest)| area > biggest) {
+                    hero = Some((index, area));
+                }
+            }
+        }
+
+        hints.push(LoadingHint {
+            tag_name,
+            url,
+            occurrence: *occurrence,
+            lazy: !is_eager,
+            hero: false,
+        });
+        *occurrence += 1;
+    }
+
+    if let Some((index, _)) = hero {
+        hints[index].hero = true;
+    }
+
+    let deferred_bytes = hints.iter()
+        .zip(&sizes)
+        .filter(|(hint, _)| hint.lazy)
+        .map(|(_, size)| size)
+        .sum();
+
+    LazyLoadingPlan { hints, deferred_bytes }
+}
+
+/// Add lazy-loading and priority hints to the images and iframes of a local HTML file
+pub async fn add_lazy_loading(html: &str, file_path: &str, viewport: (u32, u32), eager_images: usize, fetched: &FetchedResources) {
+    use colored::*;
+
+    let document = Html::parse_document(html);
+    let plan = plan_lazy_loading(&document, file_path, viewport, eager_images, fetched).await;
+
+    add_loading_attributes(file_path, &plan.hints);
+
+    if let Some(hero) = plan.hints.iter().find(|hint| hint.hero) {
+        println!("   Hero image (fetchpriority=\"high\"): {}", hero.url);
+    }
+    println!("   Deferred from the initial load: {} bytes ({:.2} KB)",
+             plan.deferred_bytes.to_string().green().bold(),
+             plan.deferred_bytes as f64 / 1024.0);
+}
@@ -1,38 +1,98 @@
-use std::{convert, env};
+use std::env;
 use std::io::{stdin,stdout,Write};
 use regex::Regex;
 
 mod resource_extractor;
 use resource_extractor::extract_ressources;
+use resource_extractor::extract_css_ressources;
+use resource_extractor::FetchedResources;
 
 mod output;
 use output::print_result;
 use output::print_css_analysis;
+use output::print_css_coverage;
+use output::print_image_size_analysis;
+use output::print_font_analysis;
+use output::print_script_analysis;
+use output::print_library_analysis;
+use output::print_third_party_analysis;
+use output::print_render_blocking_analysis;
+
+mod options;
+use options::parse_args;
 
 mod url_resolver;
 use url_resolver::is_local_path;
+use url_resolver::resolve_url;
+
+mod css_parser;
 
 mod css_analyzer;
 
+mod css_coverage;
+
+mod css_minifier;
+use css_minifier::minify_stylesheets;
+
+mod js_library_analyzer;
+
+mod js_minifier;
+use js_minifier::minify_scripts;
+
+mod css_purger;
+use css_purger::{Safelist, purge_css};
+
+mod critical_css;
+use critical_css::inline_critical_css;
+
+mod render_blocking_analyzer;
+
+mod third_party_analyzer;
+
+mod lazy_loading;
+use lazy_loading::add_lazy_loading;
+
+mod page_collector;
+use page_collector::{PageSources, collect_pages};
+
+mod image_analyzer;
+
+mod woff2_encoder;
+
+mod font_analyzer;
+use font_analyzer::write_font_subsets;
+
+mod image_metadata;
+use image_metadata::MetadataOptions;
+
+mod image_cache;
+use image_cache::ImageCache;
+
 mod image_converter;
 use image_converter::convert_images_to_webp;
+use image_converter::remove_converted_sources;
+use image_converter::generate_responsive_variants;
+use image_converter::estimate_remote_image_savings;
+
+mod svg_optimizer;
+use svg_optimizer::optimize_svgs;
 
 mod html_manager;
 use html_manager::minify_html_content;
 use html_manager::change_html_image_urls;
+use html_manager::add_srcset_to_images;
+use html_manager::add_image_dimensions;
 
 #[tokio::main]
 async fn main() {
     let html: String;
     let url: String;
     let mut local: bool = false;
-    let mut images_urls: Vec<String> = Vec::new();
     
     let args: Vec<String> = env::args().collect();
+    let options = parse_args(&args);
     
-    if args.len() > 1 {
-        let input = &args[1];
-        
+    if let Some(input) = &options.input {
         if is_local_path(input) {
             // Local file mode
             local = true;
@@ -63,53 +123,197 @@ async fn main() {
     
     let mut total_size = html_size;
     let mut total_requests = 1;
+    let mut fetched = FetchedResources::default();
     
     // Extract CSS files
     let css_selector = scraper::Selector::parse("link[rel='stylesheet']").unwrap();
-    let css_count = extract_ressources(css_selector, &document, &url, "href",&mut images_urls, "CSS",  &mut total_size).await;
+    let mut css_count = extract_ressources(css_selector, &document, &url, "href", "CSS",  &mut total_size, &mut fetched).await;
     total_requests += css_count;
 
     // Extract JS files
     let js_selector = scraper::Selector::parse("script[src]").unwrap();
-    let js_count = extract_ressources(js_selector, &document, &url, "src", &mut images_urls, "JS",  &mut total_size).await;
+    let script_urls: Vec<String> = document.select(&js_selector)
+        .filter_map(|element| element.value().attr("src"))
+        .map(|src| resolve_url(&url, src))
+        .collect();
+    let js_count = extract_ressources(js_selector, &document, &url, "src", "JS",  &mut total_size, &mut fetched).await;
     total_requests += js_count;
 
     // Extract images
     let img_selector = scraper::Selector::parse("img[src]").unwrap();
-    let img_count = extract_ressources(img_selector, &document, &url, "src", &mut images_urls, "Image", &mut total_size).await;
+    let images_urls: Vec<String> = document.select(&img_selector)
+        .filter_map(|element| element.value().attr("src"))
+        .map(|src| resolve_url(&url, src))
+        .collect();
+    let mut img_count = extract_ressources(img_selector, &document, &url, "src", "Image", &mut total_size, &mut fetched).await;
     total_requests += img_count;
 
     // Extract fonts
     let font_selector = scraper::Selector::parse("link[rel='preload'][as='font'], link[href$='.woff'], link[href$='.woff2']").unwrap();
-    let font_count = extract_ressources(font_selector, &document, &url, "href", &mut images_urls, "Font", &mut total_size).await;
+    let linked_fonts: Vec<String> = document.select(&font_selector)
+        .filter_map(|element| element.value().attr("href"))
+        .map(|href| resolve_url(&url, href))
+        .collect();
+    let mut font_count = extract_ressources(font_selector, &document, &url, "href", "Font", &mut total_size, &mut fetched).await;
     total_requests += font_count;
 
-    let css_analysis = css_analyzer::analyze_css(&document, &url).await;
+    // Imports, fonts and images loaded from the stylesheets are only found by parsing the CSS
+    let counted_urls: Vec<String> = linked_fonts.into_iter().chain(images_urls.iter().cloned()).collect();
+    let mut stylesheets = css_analyzer::collect_stylesheets(&document, &url, &mut fetched).await;
+    let css_ressources = extract_css_ressources(&stylesheets, &counted_urls, &mut total_size, &mut fetched).await;
+    css_count += css_ressources.stylesheets;
+    img_count += css_ressources.images;
+    font_count += css_ressources.fonts;
+    total_requests += css_ressources.stylesheets + css_ressources.images + css_ressources.fonts;
+
+    let css_analysis = css_analyzer::analyze_css(&document, &stylesheets);
     print_result(total_requests, css_count, js_count, img_count, font_count, total_size);
     print_css_analysis(&css_analysis);
 
-    let mut converted_urls: Vec<String> = Vec::new();
+    // Other pages of the site, so CSS used elsewhere is not reported as dead
+    let page_sources = PageSources {
+        urls: &options.pages,
+        directory: options.pages_dir.as_deref(),
+        crawl: options.crawl,
+    };
+    let pages = collect_pages(&url, &html, &page_sources).await;
+    if pages.len() > 1 {
+        let css_coverage = css_coverage::analyze_css_coverage(&pages, &mut fetched).await;
+        print_css_coverage(&css_coverage);
+    }
+
+    let image_size_analysis = image_analyzer::analyze_image_sizes(&document, &url, &fetched).await;
+    print_image_size_analysis(&image_size_analysis);
+
+    let font_analysis = font_analyzer::analyze_fonts(&document, &stylesheets, &fetched).await;
+    print_font_analysis(&font_analysis);
+
+    let script_analysis = js_minifier::analyze_scripts(&document, &url, &fetched).await;
+    print_script_analysis(&script_analysis);
+
+    let library_analysis = js_library_analyzer::analyze_libraries(&script_urls, &fetched).await;
+    print_library_analysis(&library_analysis);
+
+    let third_party_analysis = third_party_analyzer::analyze_third_parties(&document, &url, &stylesheets, &fetched).await;
+    print_third_party_analysis(&third_party_analysis);
+
+    let render_blocking_analysis = render_blocking_analyzer::analyze_render_blocking(&document, &url, &stylesheets, &fetched).await;
+    print_render_blocking_analysis(&render_blocking_analysis);
 
     if local {
-        println!("Do you wish to convert images to webp format? (It would save space) (y/n): ");
-        let mut choice=String::new();
-        stdin().read_line(&mut choice).expect("Failed to read input");
-        if choice.trim().to_lowercase() == "y" {
-            converted_urls = convert_images_to_webp(&images_urls);
-            change_html_image_urls(&url, &converted_urls);
+        // Images currently referenced by the page, updated once converted
+        let mut local_images = images_urls.clone();
+
+        if ask_yes_no("Do you wish to convert images to webp format? (It would save space) (y/n): ") {
+            let metadata_options = MetadataOptions {
+                keep_copyright: options.keep_copyright,
+                keep_color_profile: options.keep_color_profile,
+            };
+            let cache = if options.no_cache {
+                None
+            } else {
+                let cache_dir = options.cache_dir.as_deref().unwrap_or(image_cache::DEFAULT_CACHE_DIR);
+                match ImageCache::open(cache_dir) {
+                    Ok(cache) => Some(cache),
+                    Err(e) => {
+                        println!("⚠️  Image cache disabled, cannot use {}: {}", cache_dir, e);
+                        None
+                    }
+                }
+            };
+            let converted_images = convert_images_to_webp(&images_urls, &metadata_options, options.threads, cache.as_ref());
+            change_html_image_urls(&url, &converted_images);
+            remove_converted_sources(&converted_images);
+            local_images = images_urls.iter()
+                .map(|image| {
+                    converted_images.iter()
+                        .find(|(source, _)| source == image)
+                        .map(|(_, webp_path)| webp_path.clone())
+                        .unwrap_or_else(|| image.clone())
+                })
+                .collect();
+        }
+
+        if ask_yes_no("Do you wish to generate responsive image variants (srcset)? (y/n): ") {
+            let responsive_images = generate_responsive_variants(&local_images);
+            add_srcset_to_images(&url, &responsive_images);
         }
 
-        println!("Do you wish to minify the local file? (y/n): ");
-        let mut choice=String::new();
-        stdin().read_line(&mut choice).expect("Failed to read input");
-        if choice.trim().to_lowercase() == "y" {
+        if ask_yes_no("Do you wish to add the missing width/height attributes to images? (y/n): ") {
+            add_image_dimensions(&url);
+        }
+
+        if ask_yes_no("Do you wish to optimize SVG images? (y/n): ") {
+            let updated_html = std::fs::read_to_string(&url).expect("Failed to read file");
+            optimize_svgs(&updated_html, &document, &url, true).await;
+        }
+
+        if ask_yes_no("Do you wish to purge unused CSS? (y/n): ") {
+            match Safelist::new(&options.safelist) {
+                Ok(safelist) => {
+                    purge_css(&pages, &url, &stylesheets, &safelist);
+                    // The page now links the purged copies
+                    let updated_html = std::fs::read_to_string(&url).expect("Failed to read file");
+                    stylesheets = css_analyzer::collect_stylesheets(&scraper::Html::parse_document(&updated_html), &url, &mut fetched).await;
+                },
+                Err(e) => println!("❌ {}", e),
+            }
+        }
+
+        if ask_yes_no("Do you wish to inline critical CSS and load stylesheets asynchronously? (y/n): ") {
+            let updated_html = std::fs::read_to_string(&url).expect("Failed to read file");
+            let viewport = options.viewport.unwrap_or(critical_css::DEFAULT_VIEWPORT);
+            inline_critical_css(&updated_html, &url, &stylesheets, viewport, true);
+        }
+
+        if ask_yes_no("Do you wish to lazy-load the images and iframes below the fold? (y/n): ") {
+            let updated_html = std::fs::read_to_string(&url).expect("Failed to read file");
+            let viewport = options.viewport.unwrap_or(critical_css::DEFAULT_VIEWPORT);
+            add_lazy_loading(&updated_html, &url, viewport, options.eager_images, &fetched).await;
+        }
+
+        if ask_yes_no("Do you wish to write the subsetted WOFF2 fonts? (y/n): ") {
+            write_font_subsets(&font_analysis);
+        }
+
+        if ask_yes_no("Do you wish to minify the CSS files? (y/n): ") {
+            let updated_html = std::fs::read_to_string(&url).expect("Failed to read file");
+            minify_stylesheets(&updated_html, &url, &mut fetched).await;
+        }
+
+        if ask_yes_no("Do you wish to minify the JavaScript files? (y/n): ") {
+            let updated_html = std::fs::read_to_string(&url).expect("Failed to read file");
+            minify_scripts(&updated_html, &url);
+        }
+
+        if ask_yes_no("Do you wish to minify the local file? (y/n): ") {
             let updated_html = std::fs::read_to_string(&url).expect("Failed to read file");
             minify_html_content(&updated_html, &url);
         }
 
+    } else {
+        if ask_yes_no("Do you wish to estimate the savings of converting images to WebP/AVIF? (y/n): ") {
+            estimate_remote_image_savings(&images_urls, &fetched).await;
+        }
+
+        if ask_yes_no("Do you wish to estimate the savings of optimizing SVG images? (y/n): ") {
+            optimize_svgs(&html, &document, &url, false).await;
+        }
+
+        if ask_yes_no("Do you wish to estimate the savings of inlining critical CSS? (y/n): ") {
+            let viewport = options.viewport.unwrap_or(critical_css::DEFAULT_VIEWPORT);
+            inline_critical_css(&html, &url, &stylesheets, viewport, false);
+        }
     }
 }
 
+fn ask_yes_no(question: &str) -> bool {
+    println!("{}", question);
+    let mut choice=String::new();
+    stdin().read_line(&mut choice).expect("Failed to read input");
+    choice.trim().to_lowercase() == "y"
+}
+
 async fn get_user_url() -> String {
     let mut url_input=String::new();
     print!("Please enter a URL: ");
@@ -128,5 +332,5 @@ async fn get_user_url() -> String {
         eprintln!("Invalid URL format!");
         std::process::exit(1);
     }
-    return url_input;
+    url_input
 }
@@ -0,0 +1,126 @@
+/// Command line options, given as `green_optimizer [options] <URL or file>`
+#[derive(Debug, Default)]
+pub struct Options {
+    pub input: Option<String>,
+    /// Keep the Artist/Copyright EXIF tags when converting images
+    pub keep_copyright: bool,
+    /// Keep the ICC color profile when converting images
+    pub keep_color_profile: bool,
+    /// Worker threads used to convert images, 0 meaning one per CPU core
+    pub threads: usize,
+    /// Where already-encoded images are kept between runs
+    pub cache_dir: Option<String>,
+    pub no_cache: bool,
+    /// Selectors or `/regex/` patterns kept when purging unused CSS
+    pub safelist: Vec<String>,
+    /// Other pages of the site whose CSS usage is taken into account
+    pub pages: Vec<String>,
+    /// Directory holding other pages of the site
+    pub pages_dir: Option<String>,
+    /// Maximum number of pages reached by following links, 0 to disable
+    pub crawl: usize,
+    /// Viewport whose content gets its CSS inlined and its images loaded eagerly,
+    /// as (width, height) in CSS pixels
+    pub viewport: Option<(u32, u32)>,
+    /// Images at the top of the page never lazy-loaded, even when estimated below the fold
+    pub eager_images: usize,
+}
+
+pub fn parse_args(args: &[String]) -> Options {
+    let mut options = Options::default();
+
+    let mut args = args.iter().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--keep-copyright" => options.keep_copyright = true,
+            "--keep-color-profile" => options.keep_color_profile = true,
+            "--threads" => options.threads = parse_number(arg, args.next()),
+            "--cache-dir" => options.cache_dir = Some(parse_value(arg, args.next())),
+            "--no-cache" => options.no_cache = true,
+            "--safelist" => {
+                let patterns = parse_value(arg, args.next());
+                options.safelist.extend(split_safelist(&patterns));
+            },
+            "--pages" => {
+                let pages = parse_value(arg, args.next());
+                options.pages.extend(pages.split(',').map(|p| p.trim().to_string()).filter(|p| !p.is_empty()));
+            },
+            "--pages-dir" => options.pages_dir = Some(parse_value(arg, args.next())),
+            "--crawl" => options.crawl = parse_number(arg, args.next()),
+            "--viewport" => options.viewport = Some(parse_size(arg, args.next())),
+            "--eager-images" => options.eager_images = parse_number(arg, args.next()),
+            flag if flag.starts_with("--") => {
+                eprintln!("Unknown option: {}", flag);
+                std::process::exit(1);
+            },
+            input => options.input = Some(input.to_string()),
+        }
+    }
+
+    options
+}
+
+fn parse_number(flag: &str, value: Option<&String>) -> usize {
+    match value.and_then(|v| v.parse::<usize>().ok()) {
+        Some(number) => number,
+        None => {
+            eprintln!("Option {} expects a number", flag);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Parse a `<width>x<height>` value
+fn parse_size(flag: &str, value: Option<&String>) -> (u32, u32) {
+    let size = value
+        .and_then(|v| v.split_once(['x', 'X']))
+        .and_then(|(width, height)| Some((width.trim().parse::<u32>().ok()?, height.trim().parse::<u32>().ok()?)));
+    match size {
+        Some(size) => size,
+        None => {
+            eprintln!("Option {} expects a size such as 1366x768", flag);
+            std::process::exit(1);
+        }
+    }
+}
+
+fn parse_value(flag: &str, value: Option<&String>) -> String {
+    match value {
+        Some(value) => value.to_string(),
+        None => {
+            eprintln!("Option {} expects a value", flag);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Split a `--safelist` value on its commas, except those inside `/regex/` entries
+fn split_safelist(value: &str) -> Vec<String> {
+    let mut entries = Vec::new();
+    let mut rest = value.trim_start();
+
+    while !rest.is_empty() {
+        let end = if rest.starts_with('/') {
+            // A regex ends at the first `/` followed by a comma or the end of the value
+            rest.match_indices('/')
+                .skip(1)
+                .map(|(index, _)| index + 1)
+                .find(|&end| {
+                    let after = rest[end..].trim_start();
+                    after.is_empty() || after.starts_with(',')
+                })
+                .unwrap_or(rest.len())
+        } else {
+            rest.find(',').unwrap_or(rest.len())
+        };
+
+        let entry = rest[..end].trim();
+        if !entry.is_empty() {
+            entries.push(entry.to_string());
+        }
+        rest = rest[end..].trim_start();
+        rest = rest.strip_prefix(',').unwrap_or(rest).trim_start();
+    }
+
+    entries
+}
@@ -1,5 +1,12 @@
 use colored::*;
 use crate::css_analyzer;
+use crate::css_coverage;
+use crate::image_analyzer;
+use crate::font_analyzer;
+use crate::js_library_analyzer;
+use crate::js_minifier;
+use crate::render_blocking_analyzer;
+use crate::third_party_analyzer;
 
 pub fn print_result(total_requests: usize ,css_count: usize ,js_count: usize ,img_count: usize ,font_count: usize ,total_size: usize) {
 
@@ -8,13 +15,13 @@ pub fn print_result(total_requests: usize ,css_count: usize ,js_count: usize ,im
 
     let mut weight_text = format!("{} bytes ({:.2} KB, {:.2} MB)", total_size, total_size_kb, total_size_mb);
     let colored_weight = if total_size_mb < 2.0 {
-        weight_text = weight_text + "✓  Lightweight";
+        weight_text += "✓  Lightweight";
         weight_text.green()
     } else if total_size_mb < 4.0 {
-        weight_text = weight_text + "!  Kinda heavy";
+        weight_text += "!  Kinda heavy";
         weight_text.yellow()
     } else {
-        weight_text = weight_text + "✗  Heavy";
+        weight_text += "✗  Heavy";
         weight_text.red()
     };
     println!("\n{}", "========== SUMMARY ==========".bold().cyan());
@@ -36,6 +43,13 @@ pub fn print_css_analysis(analysis: &css_analyzer::CssAnalysis) {
     println!("Total selectors: {}", analysis.total_selectors.to_string().white());
     println!("Used selectors: {}", analysis.used_selectors.to_string().green());
     println!("Unused selectors: {}", analysis.unused_selectors.len().to_string().red());
+    if !analysis.unevaluated_selectors.is_empty() {
+        println!("Selectors that could not be evaluated (counted as used): {}", analysis.unevaluated_selectors.len().to_string().yellow());
+        for selector in analysis.unevaluated_selectors.iter().take(5) {
+            println!("  - {}", selector.bright_black());
+        }
+    }
+    println!("Total CSS size: {} bytes ({:.2} KB)", analysis.total_bytes, analysis.total_bytes as f64 / 1024.0);
     
     let usage_percent = if analysis.total_selectors > 0 {
         (analysis.used_selectors as f64 / analysis.total_selectors as f64) * 100.0
@@ -52,20 +66,232 @@ pub fn print_css_analysis(analysis: &css_analyzer::CssAnalysis) {
     };
     
     println!("CSS usage: {}", usage_colored.bold());
-    println!("Estimated waste: {} bytes ({:.2} KB)", 
-             analysis.estimated_waste.to_string().red(),
-             analysis.estimated_waste as f64 / 1024.0);
+    println!("Unused CSS: {} bytes ({:.2} KB)", 
+             analysis.unused_bytes.to_string().red(),
+             analysis.unused_bytes as f64 / 1024.0);
+    
+    if !analysis.stylesheets.is_empty() {
+        println!("\n{}", "Unused bytes per stylesheet:".yellow());
+        for stylesheet in &analysis.stylesheets {
+            let unused_percent = if stylesheet.total_bytes > 0 {
+                (stylesheet.unused_bytes as f64 / stylesheet.total_bytes as f64) * 100.0
+            } else {
+                0.0
+            };
+            println!("  - {}: {} of {} bytes ({:.1}%)", stylesheet.name, stylesheet.unused_bytes, stylesheet.total_bytes, unused_percent);
+        }
+    }
     
-    // Show first 10 unused selectors
-    if !analysis.unused_selectors.is_empty() {
-        println!("\n{}", "Top unused selectors:".yellow());
-        for (i, selector) in analysis.unused_selectors.iter().take(10).enumerate() {
-            println!("  {}. {}", i + 1, selector.bright_black());
+    // Show the 10 biggest unused rules
+    if !analysis.unused_rules.is_empty() {
+        println!("\n{}", "Biggest unused rules:".yellow());
+        for (i, rule) in analysis.unused_rules.iter().take(10).enumerate() {
+            let context = if rule.at_rules.is_empty() {
+                String::new()
+            } else {
+                format!(" (in {})", rule.at_rules.join(" "))
+            };
+            println!("  {}. {}{} - {} bytes in {}", i + 1, rule.prelude.bright_black(), context, rule.bytes, rule.stylesheet);
         }
-        if analysis.unused_selectors.len() > 10 {
-            println!("  ... and {} more", analysis.unused_selectors.len() - 10);
+        if analysis.unused_rules.len() > 10 {
+            println!("  ... and {} more", analysis.unused_rules.len() - 10);
         }
     }
     
     println!("{}", "==================================".bold().cyan());
-}
\ No newline at end of file
+}
+
+pub fn print_image_size_analysis(analysis: &image_analyzer::ImageSizeAnalysis) {
+    println!("\n{}", "========== IMAGE SIZES ==========".bold().cyan());
+    println!("Images checked: {}", analysis.checked_images.to_string().white());
+    println!("Oversized images: {}", analysis.oversized_images.len().to_string().red());
+    if analysis.unsized_images > 0 {
+        println!("Images without declared size: {}", analysis.unsized_images.to_string().yellow());
+    }
+
+    for image in &analysis.oversized_images {
+        println!("  - {}", image.url.bright_black());
+        println!("    served at {}x{}, displayed at {}x{} ({} bytes, ~{} bytes saved by resizing)",
+                 image.intrinsic_width, image.intrinsic_height,
+                 image.displayed_width, image.displayed_height,
+                 image.size,
+                 image.estimated_savings.to_string().green());
+    }
+
+    println!("Estimated savings: {} bytes ({:.2} KB)",
+             analysis.total_savings.to_string().green(),
+             analysis.total_savings as f64 / 1024.0);
+    println!("{}", "=================================".bold().cyan());
+}
+
+pub fn print_font_analysis(analysis: &font_analyzer::FontAnalysis) {
+    println!("\n{}", "========== FONTS ==========".bold().cyan());
+    println!("Fonts declared in @font-face: {}", analysis.fonts.len().to_string().white());
+    println!("Characters used by the page: {}", analysis.used_characters.to_string().white());
+
+    for font in &analysis.fonts {
+        println!("  - {} ({}, {})", font.url.bright_black(), font.family, font.format);
+        if let Some(error) = &font.error {
+            println!("    {}", format!("⚠️  {}", error).yellow());
+            continue;
+        }
+        if font.woff2_listed_later {
+            println!("    {}", "✗  The WOFF2 source is listed after this one, browsers download the first format they support".red());
+        } else if font.legacy_format {
+            println!("    {}", "✗  Served as a legacy format, WOFF2 is much smaller".red());
+        }
+        if let Some(subset) = &font.subset {
+            println!("    {} bytes, subsetted WOFF2: {} bytes (saves {} bytes)",
+                     font.size,
+                     subset.len(),
+                     font.size.saturating_sub(subset.len()).to_string().green());
+        }
+    }
+
+    println!("Total font weight: {} bytes ({:.2} KB)", analysis.total_bytes, analysis.total_bytes as f64 / 1024.0);
+    println!("Potential savings: {} bytes ({:.2} KB)",
+             analysis.total_savings.to_string().green(),
+             analysis.total_savings as f64 / 1024.0);
+    println!("{}", "===========================".bold().cyan());
+}
+
+pub fn print_css_coverage(coverage: &css_coverage::CssCoverage) {
+    println!("\n{}", format!("========== CSS COVERAGE ({} pages) ==========", coverage.pages).bold().cyan());
+
+    for stylesheet in &coverage.stylesheets {
+        println!("{} (loaded by {} of {} pages)", stylesheet.name.white().bold(), stylesheet.pages, coverage.pages);
+        println!("  Selectors: {} used on every page loading it, {} on some of them, {} on none (of {})",
+                 stylesheet.used_on_all_pages.to_string().green(),
+                 stylesheet.used_on_some_pages.len().to_string().yellow(),
+                 stylesheet.unused_selectors.len().to_string().red(),
+                 stylesheet.total_selectors);
+        println!("  Unused on every page: {} of {} bytes", stylesheet.unused_bytes.to_string().red(), stylesheet.total_bytes);
+
+        for (selector, used_on) in stylesheet.used_on_some_pages.iter().take(5) {
+            println!("    - {} used on {} of {} pages", selector.bright_black(), used_on, stylesheet.pages);
+        }
+        if stylesheet.used_on_some_pages.len() > 5 {
+            println!("    ... and {} more", stylesheet.used_on_some_pages.len() - 5);
+        }
+    }
+
+    println!("{}", "==========================================".bold().cyan());
+}
+
+pub fn print_script_analysis(analysis: &js_minifier::ScriptAnalysis) {
+    println!("\n{}", "========== JAVASCRIPT ==========".bold().cyan());
+    println!("Scripts: {}", analysis.scripts.len().to_string().white());
+
+    for script in &analysis.scripts {
+        println!("  - {}", script.name.bright_black());
+        if script.size == 0 && let Some(error) = &script.error {
+            println!("    {}", format!("⚠️  {}", error).yellow());
+            continue;
+        }
+        match script.minified_size {
+            Some(minified_size) => println!("    {} bytes, minified: {} bytes (saves {} bytes), gzipped: {} bytes",
+                                            script.size,
+                                            minified_size,
+                                            script.size.saturating_sub(minified_size).to_string().green(),
+                                            script.gzipped_size),
+            None => println!("    {} bytes, gzipped: {} bytes", script.size, script.gzipped_size),
+        }
+        if let Some(error) = &script.error {
+            println!("    {}", format!("⚠️  {}", error).yellow());
+        }
+    }
+
+    println!("Total JavaScript: {} bytes, minified: {} bytes, gzipped: {} bytes",
+             analysis.total_bytes,
+             analysis.total_minified_bytes,
+             analysis.total_gzipped_bytes.to_string().white().bold());
+    println!("{}", "================================".bold().cyan());
+}
+
+pub fn print_library_analysis(analysis: &js_library_analyzer::LibraryAnalysis) {
+    if analysis.libraries.is_empty() {
+        return;
+    }
+
+    println!("\n{}", "========== JAVASCRIPT LIBRARIES ==========".bold().cyan());
+    for library in &analysis.libraries {
+        println!("  - {} {} ({}, {} bytes)",
+                 library.name.white().bold(),
+                 library.version.as_deref().unwrap_or("(unknown version)"),
+                 library.script_url.bright_black(),
+                 library.script_size);
+    }
+
+    for duplicate in &analysis.duplicates {
+        let problem = if duplicate.versions.len() > 1 {
+            format!("✗  {} is loaded in {} versions ({})", duplicate.name, duplicate.versions.len(), duplicate.versions.join(", "))
+        } else {
+            format!("✗  {} is loaded {} times", duplicate.name, duplicate.scripts.len())
+        };
+        println!("{}", problem.red());
+        for script in &duplicate.scripts {
+            println!("    {}", script.bright_black());
+        }
+        println!("    Wasted: {} bytes", duplicate.wasted_bytes.to_string().red());
+    }
+
+    println!("Lighter alternatives:");
+    for (name, alternative) in &analysis.alternatives {
+        println!("  - {}: {}", name, alternative.green());
+    }
+    println!("{}", "==========================================".bold().cyan());
+}
+
+pub fn print_third_party_analysis(analysis: &third_party_analyzer::ThirdPartyAnalysis) {
+    let third_party_requests: usize = analysis.entities.iter().map(|usage| usage.requests).sum();
+    let third_party_bytes: usize = analysis.entities.iter().map(|usage| usage.bytes).sum();
+
+    println!("\n{}", "========== THIRD PARTIES ==========".bold().cyan());
+    println!("First party: {} requests, {} bytes ({:.2} KB)",
+             analysis.first_party_requests, analysis.first_party_bytes, analysis.first_party_bytes as f64 / 1024.0);
+    println!("Third parties: {} requests, {} bytes ({:.2} KB)",
+             third_party_requests.to_string().yellow(), third_party_bytes.to_string().yellow(), third_party_bytes as f64 / 1024.0);
+
+    for usage in &analysis.entities {
+        println!("  - {} ({}): {} requests, {} bytes",
+                 usage.name.white().bold(),
+                 usage.category.label(),
+                 usage.requests,
+                 usage.bytes.to_string().red());
+        println!("    {}", usage.hosts.join(", ").bright_black());
+    }
+    println!("{}", "===================================".bold().cyan());
+}
+
+pub fn print_render_blocking_analysis(analysis: &render_blocking_analyzer::RenderBlockingAnalysis) {
+    println!("\n{}", "========== RENDER-BLOCKING RESOURCES ==========".bold().cyan());
+    if analysis.resources.is_empty() {
+        println!("{}", "✓  Nothing blocks the first rendering".green());
+    }
+
+    for resource in &analysis.resources {
+        println!("  - {} {} ({} bytes)", resource.kind.label(), resource.url.bright_black(), resource.size.to_string().red());
+        println!("    {}", format!("→ {}", resource.suggestion).yellow());
+    }
+
+    println!("Render-blocking: {} requests, {} bytes ({:.2} KB)",
+             analysis.resources.len(),
+             analysis.total_bytes.to_string().red().bold(),
+             analysis.total_bytes as f64 / 1024.0);
+    println!("{}", "===============================================".bold().cyan());
+}
+
+/// Report the size of a file or block before and after an optimization
+pub fn print_savings(title: &str, original_size: usize, optimized_size: usize) {
+    let saved = original_size.saturating_sub(optimized_size);
+    let saved_percent = if original_size > 0 {
+        (saved as f64 / original_size as f64) * 100.0
+    } else {
+        0.0
+    };
+
+    println!("✅ {}", title);
+    println!("   Original: {} bytes", original_size);
+    println!("   Optimized: {} bytes", optimized_size);
+    println!("   Saved: {} bytes ({:.1}%)", saved, saved_percent);
+}
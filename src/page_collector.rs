@@ -0,0 +1,136 @@
+use scraper::{Html, Selector};
+use std::collections::{HashSet, VecDeque};
+use std::fs;
+use std::path::Path;
+use crate::resource_extractor::fetch_resource;
+use crate::url_resolver::{is_remote_url, resolve_url};
+
+/// A page of the audited site
+pub struct Page {
+    pub url: String,
+    pub document: Html,
+}
+
+/// Where to find the other pages of the site, besides the audited one
+#[derive(Default)]
+pub struct PageSources<'a> {
+    /// URLs or local files
+    pub urls: &'a [String],
+    /// Directory searched recursively for `.html` files
+    pub directory: Option<&'a str>,
+    /// Maximum number of pages reached by following links from the audited page, 0 to disable
+    pub crawl: usize,
+}
+
+/// Collect the audited page, then the pages given by `sources`, each page once
+pub async fn collect_pages(url: &str, html: &str, sources: &PageSources<'_>) -> Vec<Page> {
+    let mut pages = vec![Page {
+        url: url.to_string(),
+        document: Html::parse_document(html),
+    }];
+    let mut seen: HashSet<String> = HashSet::from([url.to_string()]);
+    if !is_remote_url(url) {
+        // Links to the audited page resolve to its canonical path
+        seen.insert(resolve_url(".", url));
+    }
+
+    let mut page_urls: Vec<String> = sources.urls.to_vec();
+    if let Some(directory) = sources.directory {
+        let mut files = Vec::new();
+        find_html_files(Path::new(directory), &mut files);
+        files.sort();
+        page_urls.extend(files);
+    }
+
+    for page_url in page_urls {
+        let page_url = if is_remote_url(&page_url) {
+            page_url
+        } else {
+            resolve_url(".", &page_url)
+        };
+        if !seen.insert(page_url.clone()) {
+            continue;
+        }
+        match fetch_page(&page_url).await {
+            Ok(page) => pages.push(page),
+            Err(e) => println!("⚠️  Failed to load page {}: {}", page_url, e),
+        }
+    }
+
+    if sources.crawl > 0 {
+        crawl_links(&mut pages, &mut seen, sources.crawl).await;
+    }
+
+    pages
+}
+
+/// Follow the links of the audited page, breadth first, staying on the same site,
+/// until `max_pages` pages are added
+async fn crawl_links(pages: &mut Vec<Page>, seen: &mut HashSet<String>, max_pages: usize) {
+    let link_selector = Selector::parse("a[href]").unwrap();
+    let site_url = pages[0].url.clone();
+    let mut crawled = 0;
+    let mut queue: VecDeque<String> = VecDeque::new();
+
+    let enqueue_links = |page: &Page, queue: &mut VecDeque<String>, seen: &mut HashSet<String>| {
+        for element in page.document.select(&link_selector) {
+            let Some(href) = element.value().attr("href") else { continue };
+            if href.starts_with('#') || href.starts_with("mailto:") || href.starts_with("tel:") || href.starts_with("javascript:") {
+                continue;
+            }
+            let link = resolve_url(&page.url, href);
+            let link = link.split('#').next().unwrap_or("").to_string();
+            if is_same_site(&site_url, &link) && seen.insert(link.clone()) {
+                queue.push_back(link);
+            }
+        }
+    };
+
+    enqueue_links(&pages[0], &mut queue, seen);
+    while crawled < max_pages {
+        let Some(link) = queue.pop_front() else { break };
+        match fetch_page(&link).await {
+            Ok(page) => {
+                enqueue_links(&page, &mut queue, seen);
+                pages.push(page);
+                crawled += 1;
+            },
+            Err(e) => println!("⚠️  Failed to load page {}: {}", link, e),
+        }
+    }
+}
+
+fn is_same_site(site_url: &str, link: &str) -> bool {
+    if is_remote_url(site_url) {
+        let host = |u: &str| url::Url::parse(u).ok().and_then(|u| u.host_str().map(str::to_string));
+        is_remote_url(link) && host(site_url).is_some() && host(site_url) == host(link)
+    } else {
+        let path = link.to_lowercase();
+        !is_remote_url(link) && (path.ends_with(".html") || path.ends_with(".htm")) && Path::new(link).is_file()
+    }
+}
+
+async fn fetch_page(url: &str) -> Result<Page, Box<dyn std::error::Error>> {
+    let content = fetch_resource(url).await?;
+    let html = String::from_utf8_lossy(&content);
+    Ok(Page {
+        url: url.to_string(),
+        document: Html::parse_document(&html),
+    })
+}
+
+fn find_html_files(directory: &Path, files: &mut Vec<String>) {
+    let Ok(entries) = fs::read_dir(directory) else {
+        println!("⚠️  Failed to read directory {}", directory.display());
+        return;
+    };
+
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.is_dir() {
+            find_html_files(&path, files);
+        } else if path.extension().and_then(|e| e.to_str()).is_some_and(|e| e.eq_ignore_ascii_case("html") || e.eq_ignore_ascii_case("htm")) {
+            files.push(path.to_string_lossy().to_string());
+        }
+    }
+}
@@ -0,0 +1,170 @@
+use scraper::{Html, Selector};
+use std::collections::HashMap;
+use crate::css_analyzer::Stylesheet;
+use crate::css_parser::{CssRuleKind, parse_stylesheet};
+use crate::resource_extractor::FetchedResources;
+use crate::third_party_analyzer::site_of;
+use crate::url_resolver::{is_remote_url, resolve_url};
+
+pub enum BlockingKind {
+    Stylesheet,
+    Script,
+}
+
+impl BlockingKind {
+    pub fn label(&self) -> &'static str {
+        match self {
+            BlockingKind::Stylesheet => "CSS",
+            BlockingKind::Script => "JS",
+        }
+    }
+}
+
+/// A resource the browser downloads and processes before displaying the page
+pub struct BlockingResource {
+    pub url: String,
+    pub kind: BlockingKind,
+    pub size: usize,
+    pub suggestion: String,
+}
+
+pub struct RenderBlockingAnalysis {
+    /// In document order, imported stylesheets after the stylesheet importing them
+    pub resources: Vec<BlockingResource>,
+    pub total_bytes: usize,
+}
+
+/// Whether a `media` attribute applies to every screen, so the stylesheet blocks the rendering
+fn is_unrestricted_media(media: Option<&str>) -> bool {
+    media.is_none_or(|media| {
+        media.split(',')
+            .map(|query| query.trim().to_lowercase())
+            .any(|query| query.is_empty() || query == "all" || query == "screen")
+    })
+}
+
+/// The media query shared by all the rules of a stylesheet, when it is entirely wrapped
+/// in the same `@media` block
+fn single_media_query(css: &str) -> Option<String> {
+    let rules = parse_stylesheet(css);
+    let mut top_level = rules.iter().filter(|rule| rule.parent.is_none());
+    let first = top_level.next()?;
+    let is_media = |kind: &CssRuleKind| matches!(kind, CssRuleKind::AtRule(name) if name == "media");
+    if !is_media(&first.kind) {
+        return None;
+    }
+    top_level.all(|rule| is_media(&rule.kind) && rule.prelude == first.prelude)
+        .then(|| first.prelude.clone())
+}
+
+fn stylesheet_suggestion(css: &str) -> String {
+    match single_media_query(css) {
+        Some(media) => format!("All its rules are in @media {}: add media=\"{}\" to the <link> so it stops blocking elsewhere", media, media),
+        None => "Inline the critical CSS and load the rest with <link rel=\"preload\" as=\"style\">, or split the rules only some screens need into a stylesheet with a media attribute".to_string(),
+    }
+}
+
+fn script_suggestion(script_url: &str, base_url: &str, content: &str) -> String {
+    if content.contains("document.write") {
+        return "Uses document.write, which cannot run asynchronously: replace it with DOM APIs, then add defer".to_string();
+    }
+    // Subdomains of the page site, like a static or CDN host, serve first-party scripts
+    let site = |u: &str| url::Url::parse(u).ok().and_then(|u| u.host_str().map(site_of));
+    if is_remote_url(script_url) && site(script_url) != site(base_url) {
+        "Add async, a third-party script rarely depends on the page scripts".to_string()
+    } else {
+        "Add defer, it keeps the execution order of the scripts and runs them once the page is parsed".to_string()
+    }
+}
+
+/// Imported stylesheets below `parent`, depth first, each after the stylesheet importing it
+fn push_imports(resources: &mut Vec<BlockingResource>, imports: &HashMap<&str, Vec<&Stylesheet>>, parent: &str, base_url: &str) {
+    // Imports chain requests: the browser only finds them after downloading the parent
+    let mut pending = vec![parent.to_string()];
+    while let Some(parent) = pending.pop() {
+        let importer = if parent == base_url { "a <style> block of the page".to_string() } else { parent.clone() };
+        for imported in imports.get(parent.as_str()).into_iter().flatten() {
+            resources.push(BlockingResource {
+                url: imported.url.clone(),
+                kind: BlockingKind::Stylesheet,
+                size: imported.content.len(),
+                suggestion: format!("Imported by {}, so downloaded late: link it from the page or preload it with <link rel=\"preload\" as=\"style\">", importer),
+            });
+            pending.push(imported.url.clone());
+        }
+    }
+}
+
+/// Report the stylesheets and scripts of `<head>` that block the first rendering, reading
+/// their content from the page `stylesheets` and the scripts already `fetched`
+pub async fn analyze_render_blocking(document: &Html, base_url: &str, stylesheets: &[Stylesheet], fetched: &FetchedResources) -> RenderBlockingAnalysis {
+    let head_selector = Selector::parse("head link[rel~='stylesheet' i][href], head script[src], head style").unwrap();
+    let mut resources: Vec<BlockingResource> = Vec::new();
+
+    // Stylesheets imported by each stylesheet, `<style>` blocks importing under the page URL
+    let mut imports: HashMap<&str, Vec<&Stylesheet>> = HashMap::new();
+    for stylesheet in stylesheets {
+        if let Some(parent) = &stylesheet.imported_by {
+            imports.entry(parent.as_str()).or_default().push(stylesheet);
+        }
+    }
+    let mut inline_imports_done = false;
+
+    for element in document.select(&head_selector) {
+        let element = element.value();
+
+        if element.name() == "style" {
+            if !inline_imports_done && is_unrestricted_media(element.attr("media")) {
+                push_imports(&mut resources, &imports, base_url, base_url);
+                inline_imports_done = true;
+            }
+        } else if element.name() == "link" {
+            // Alternate stylesheets are only applied once the user picks them, they do not block
+            let alternate = element.attr("rel")
+                .is_some_and(|rel| rel.split_ascii_whitespace().any(|token| token.eq_ignore_ascii_case("alternate")));
+            if alternate || element.attr("disabled").is_some() || !is_unrestricted_media(element.attr("media")) {
+                continue;
+            }
+            let Some(href) = element.attr("href") else { continue };
+            let stylesheet_url = resolve_url(base_url, href);
+            let content = stylesheets.iter()
+                .find(|stylesheet| !stylesheet.inline && stylesheet.url == stylesheet_url)
+                .map(|stylesheet| stylesheet.content.as_str())
+                .unwrap_or("");
+
+            resources.push(BlockingResource {
+                url: stylesheet_url.clone(),
+                kind: BlockingKind::Stylesheet,
+                size: content.len(),
+                suggestion: stylesheet_suggestion(content),
+            });
+            push_imports(&mut resources, &imports, &stylesheet_url, base_url);
+        } else {
+            let script_type = element.attr("type").map(|t| t.trim().to_lowercase());
+            if element.attr("async").is_some() || element.attr("defer").is_some() || script_type.as_deref() == Some("module") {
+                continue;
+            }
+            // Data blocks are not executed
+            if script_type.is_some_and(|t| !t.is_empty() && !t.contains("javascript") && !t.contains("ecmascript")) {
+                continue;
+            }
+            let Some(src) = element.attr("src") else { continue };
+            let script_url = resolve_url(base_url, src);
+            let content = fetched.get(&script_url).await
+                .map(|content| String::from_utf8_lossy(&content).to_string())
+                .unwrap_or_default();
+
+            resources.push(BlockingResource {
+                suggestion: script_suggestion(&script_url, base_url, &content),
+                url: script_url,
+                kind: BlockingKind::Script,
+                size: content.len(),
+            });
+        }
+    }
+
+    RenderBlockingAnalysis {
+        total_bytes: resources.iter().map(|resource| resource.size).sum(),
+        resources,
+    }
+}
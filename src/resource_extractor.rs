@@ -1,31 +1,51 @@
+use crate::css_analyzer::{Stylesheet, extract_css_references, CssReferenceKind};
 use crate::url_resolver::{resolve_url, is_remote_url};
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
 use std::fs;
 
+/// Content of the resources downloaded while counting the page requests, by resolved URL,
+/// so the analyzers do not download them again
+#[derive(Default)]
+pub struct FetchedResources {
+    contents: HashMap<String, Vec<u8>>,
+}
+
+impl FetchedResources {
+    /// Content of a resource, fetched only if it was not downloaded yet
+    pub async fn get(&self, resource_url: &str) -> Result<Cow<'_, [u8]>, Box<dyn std::error::Error>> {
+        match self.contents.get(resource_url) {
+            Some(content) => Ok(Cow::Borrowed(content)),
+            None => Ok(Cow::Owned(fetch_resource(resource_url).await?)),
+        }
+    }
+
+    /// Content of a resource, fetched and kept for the next callers if it was not downloaded yet
+    pub async fn load(&mut self, resource_url: &str) -> Result<&[u8], Box<dyn std::error::Error>> {
+        if !self.contents.contains_key(resource_url) {
+            let content = fetch_resource(resource_url).await?;
+            self.contents.insert(resource_url.to_string(), content);
+        }
+        Ok(&self.contents[resource_url])
+    }
+}
+
 pub async fn extract_ressources(
     selector: scraper::Selector,
     document: &scraper::Html,
     url: &str,
     attr_name: &str,
-    images_urls: &mut Vec<String>,
     resource_type: &str,
-    total_size: &mut usize
+    total_size: &mut usize,
+    fetched: &mut FetchedResources
 ) -> usize {
     let mut request_count = 0;
     
     for element in document.select(&selector) {
         if let Some(src) = element.value().attr(attr_name) {
-            if resource_type == "Image" {
-                images_urls.push(resolve_url(url, src));
-            }
             let resource_url = resolve_url(url, src);
             
-            let size_result = if is_remote_url(&resource_url) {
-                get_remote_resource_size(&resource_url).await
-            } else {
-                get_local_resource_size(&resource_url)
-            };
-            
-            if let Ok(size) = size_result {
+            if let Ok(size) = fetched.load(&resource_url).await.map(|content| content.len()) {
                 *total_size += size;
                 request_count += 1;
                 println!("{}: {} - {} bytes", resource_type, src, size);
@@ -38,13 +58,68 @@ pub async fn extract_ressources(
     request_count
 }
 
-async fn get_remote_resource_size(url: &str) -> Result<usize, Box<dyn std::error::Error>> {
+/// Requests made by the page stylesheets, by kind
+#[derive(Default)]
+pub struct CssRessourceCounts {
+    pub stylesheets: usize,
+    pub fonts: usize,
+    pub images: usize,
+}
+
+/// Count the stylesheets pulled in by `@import`, the fonts declared in `@font-face`
+/// rules and the images loaded through `url()`, following imports recursively.
+/// Resources in `counted_urls` were already found in the HTML and are skipped.
+pub async fn extract_css_ressources(
+    stylesheets: &[Stylesheet],
+    counted_urls: &[String],
+    total_size: &mut usize,
+    fetched: &mut FetchedResources
+) -> CssRessourceCounts {
+    let mut counts = CssRessourceCounts::default();
+    let mut seen: HashSet<String> = counted_urls.iter().cloned().collect();
+
+    for stylesheet in stylesheets {
+        if let Some(parent) = &stylesheet.imported_by {
+            let size = stylesheet.content.len();
+            *total_size += size;
+            counts.stylesheets += 1;
+            println!("CSS: {} - {} bytes (@import in {})", stylesheet.url, size, parent);
+        }
+
+        for reference in extract_css_references(&stylesheet.content, &stylesheet.url) {
+            // Imported stylesheets are counted when their own turn comes
+            if reference.kind == CssReferenceKind::Stylesheet || !seen.insert(reference.url.clone()) {
+                continue;
+            }
+
+            let resource_type = reference.kind.label();
+            if let Ok(size) = fetched.load(&reference.url).await.map(|content| content.len()) {
+                *total_size += size;
+                match reference.kind {
+                    CssReferenceKind::Font => counts.fonts += 1,
+                    _ => counts.images += 1,
+                }
+                println!("{}: {} - {} bytes (in {})", resource_type, reference.url, size, stylesheet.url);
+            } else {
+                println!("{}: {} - ⚠️  Failed to fetch (in {})", resource_type, reference.url, stylesheet.url);
+            }
+        }
+    }
+
+    counts
+}
+
+async fn fetch_remote_resource(url: &str) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
     let response = reqwest::get(url).await?;
     let bytes = response.bytes().await?;
-    Ok(bytes.len())
+    Ok(bytes.to_vec())
 }
 
-fn get_local_resource_size(path: &str) -> Result<usize, Box<dyn std::error::Error>> {
-    let content = fs::read(path)?;
-    Ok(content.len())
-}
\ No newline at end of file
+/// Fetch the content of a resolved resource, from the network or from disk
+pub async fn fetch_resource(resource_url: &str) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    if is_remote_url(resource_url) {
+        fetch_remote_resource(resource_url).await
+    } else {
+        Ok(fs::read(resource_url)?)
+    }
+}
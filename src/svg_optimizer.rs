@@ -0,0 +1,361 @@
+use regex::{Captures, Regex};
+use scraper::{Html, Selector};
+use std::collections::HashSet;
+use std::fs;
+use crate::output::print_savings;
+use crate::resource_extractor::fetch_resource;
+use crate::url_resolver::{is_remote_url, resolve_url};
+
+/// Decimals kept in coordinates and path data
+const NUMERIC_PRECISION: usize = 3;
+
+/// Attributes holding a single length or coordinate
+const NUMERIC_ATTRIBUTES: [&str; 17] = [
+    "x", "y", "x1", "y1", "x2", "y2", "cx", "cy", "r", "rx", "ry",
+    "width", "height", "stroke-width", "font-size", "dx", "dy",
+];
+
+/// Attributes that never change the rendering. `xml:space` is kept, it changes how the
+/// whitespace of `<text>` is rendered.
+const REDUNDANT_ATTRIBUTES: [&str; 3] = ["version", "enable-background", "baseProfile"];
+
+/// Namespaces written by Inkscape, Sketch, Illustrator and friends
+const EDITOR_NAMESPACES: [&str; 5] = ["inkscape", "sodipodi", "sketch", "i", "serif"];
+
+/// Non-inherited attributes set to their default value, by element (`*` for any element).
+/// Positions default to 0 only on these elements: filter and mask regions start at -10%,
+/// and on text `x="0"` resets the position of a line.
+const DEFAULT_VALUES: [(&str, &str, &str); 9] = [
+    ("*", "opacity", "1"),
+    ("rect", "x", "0"), ("rect", "y", "0"),
+    ("image", "x", "0"), ("image", "y", "0"),
+    ("use", "x", "0"), ("use", "y", "0"),
+    ("svg", "x", "0"), ("svg", "y", "0"),
+];
+
+/// Elements only rendered when referenced from elsewhere, so they work inside hidden containers
+const REFERENCEABLE_ELEMENTS: [&str; 9] = [
+    "symbol", "defs", "linearGradient", "radialGradient", "clipPath", "mask", "filter", "marker", "pattern",
+];
+
+pub fn optimize_svg(svg: &str) -> String {
+    // Comments, XML declaration and doctype are useless once served
+    let comment_re = Regex::new(r"(?s)<!--.*?-->").unwrap();
+    let prolog_re = Regex::new(r"(?s)<\?xml.*?\?>|<!DOCTYPE[^>]*>").unwrap();
+    let mut optimized = comment_re.replace_all(svg, "").to_string();
+    optimized = prolog_re.replace_all(&optimized, "").to_string();
+
+    optimized = remove_elements(&optimized, |name, attributes, content| {
+        is_editor_name(name) || name == "metadata" || is_hidden(attributes, content)
+    });
+
+    let tag_re = Regex::new(r"<([A-Za-z][\w:.-]*)((?:\s+[^\s=/>]+(?:\s*=\s*(?:\x22[^\x22]*\x22|'[^']*'))?)*)\s*(/?)>").unwrap();
+    optimized = tag_re.replace_all(&optimized, |cap: &Captures| {
+        let attributes = parse_attributes(&cap[2])
+            .into_iter()
+            .filter(|(name, value)| !is_redundant_attribute(&cap[1], name, value))
+            .map(|(name, value)| {
+                let value = shorten_attribute(&name, &value);
+                format!(" {}=\"{}\"", name, value)
+            })
+            .collect::<String>();
+        format!("<{}{}{}>", &cap[1], attributes, if cap[3].is_empty() { "" } else { "/" })
+    }).to_string();
+
+    // Whitespace between tags is only meaningful around text
+    if !optimized.contains("<text") {
+        let between_tags_re = Regex::new(r">\s+<").unwrap();
+        optimized = between_tags_re.replace_all(&optimized, "><").to_string();
+    }
+
+    optimized.trim().to_string()
+}
+
+fn is_editor_name(name: &str) -> bool {
+    name.split_once(':')
+        .map(|(prefix, _)| EDITOR_NAMESPACES.contains(&prefix))
+        .unwrap_or(false)
+}
+
+fn is_hidden(attributes: &[(String, String)], content: &str) -> bool {
+    // Elements with an id may be referenced (by `<use>`, CSS or JS) and shown elsewhere,
+    // hidden sprite sheets are only containers for such elements
+    if attributes.iter().any(|(name, _)| name == "id") || has_referenceable_content(content) {
+        return false;
+    }
+
+    attributes.iter().any(|(name, value)| {
+        let value = value.replace(' ', "");
+        (name == "display" && value == "none")
+            || (name == "visibility" && value == "hidden")
+            || (name == "style" && (value.contains("display:none") || value.contains("visibility:hidden")))
+    })
+}
+
+/// Whether `content` holds an element with an id or an element meant to be referenced
+fn has_referenceable_content(content: &str) -> bool {
+    let tag_re = Regex::new(r"<([A-Za-z][\w:.-]*)([^>]*)>").unwrap();
+    tag_re.captures_iter(content).any(|cap| {
+        REFERENCEABLE_ELEMENTS.contains(&&cap[1])
+            || parse_attributes(&cap[2]).iter().any(|(name, _)| name == "id")
+    })
+}
+
+fn is_redundant_attribute(element: &str, name: &str, value: &str) -> bool {
+    if REDUNDANT_ATTRIBUTES.contains(&name) || is_editor_name(name) {
+        return true;
+    }
+    // Editor namespace declarations, e.g. `xmlns:inkscape`
+    if let Some(prefix) = name.strip_prefix("xmlns:") {
+        return EDITOR_NAMESPACES.contains(&prefix);
+    }
+    DEFAULT_VALUES.iter().any(|(default_element, default_name, default_value)| {
+        (*default_element == "*" || element == *default_element) && name == *default_name && value.trim() == *default_value
+    })
+}
+
+fn shorten_attribute(name: &str, value: &str) -> String {
+    match name {
+        "d" => shorten_path_data(value),
+        "points" | "viewBox" | "transform" => shorten_numbers(value),
+        _ if NUMERIC_ATTRIBUTES.contains(&name) => shorten_numbers(value),
+        _ => value.to_string(),
+    }
+}
+
+fn parse_attributes(attributes: &str) -> Vec<(String, String)> {
+    let attribute_re = Regex::new(r#"([^\s=/>]+)(?:\s*=\s*(?:"([^"]*)"|'([^']*)'))?"#).unwrap();
+    attribute_re.captures_iter(attributes)
+        .map(|cap| {
+            let value = cap.get(2).or(cap.get(3)).map(|m| m.as_str()).unwrap_or("");
+            // Single-quoted values are written back between double quotes
+            (cap[1].to_string(), value.replace('"', "&quot;"))
+        })
+        .collect()
+}
+
+/// Round every number of a value to `NUMERIC_PRECISION` decimals
+fn shorten_numbers(value: &str) -> String {
+    let number_re = Regex::new(r"-?(?:\d+\.?\d*|\.\d+)(?:[eE][-+]?\d+)?").unwrap();
+    number_re.replace_all(value, |cap: &Captures| format_number(&cap[0])).to_string()
+}
+
+fn format_number(number: &str) -> String {
+    let Ok(value) = number.parse::<f64>() else { return number.to_string() };
+
+    let mut formatted = format!("{:.*}", NUMERIC_PRECISION, value);
+    if formatted.contains('.') {
+        formatted = formatted.trim_end_matches('0').trim_end_matches('.').to_string();
+    }
+    if formatted == "-0" {
+        formatted = "0".to_string();
+    }
+    // 0.5 -> .5 and -0.5 -> -.5
+    if let Some(rest) = formatted.strip_prefix("0.") {
+        formatted = format!(".{}", rest);
+    } else if let Some(rest) = formatted.strip_prefix("-0.") {
+        formatted = format!("-.{}", rest);
+    }
+    formatted
+}
+
+/// Round path coordinates and write them with the fewest separators,
+/// dropping command letters repeated implicitly
+fn shorten_path_data(d: &str) -> String {
+    // Arc flags may be written without separators ("a1 1 0 01 5 5"), leave those paths alone
+    if d.contains(['A', 'a']) {
+        return d.trim().to_string();
+    }
+
+    let token_re = Regex::new(r"[MmZzLlHhVvCcSsQqTtAa]|-?(?:\d+\.?\d*|\.\d+)(?:[eE][-+]?\d+)?").unwrap();
+
+    let mut output = String::new();
+    let mut previous_command: Option<char> = None;
+    // Whether the last thing written is a number, and if it has a decimal point
+    let mut last_number: Option<bool> = None;
+
+    for token in token_re.find_iter(d).map(|m| m.as_str()) {
+        let first = token.chars().next().unwrap();
+        if first.is_ascii_alphabetic() {
+            // After a moveto the implicit command is a lineto, so it cannot be dropped
+            let repeats = previous_command == Some(first) && !matches!(first, 'M' | 'm' | 'Z' | 'z');
+            if !repeats {
+                output.push(first);
+                last_number = None;
+            }
+            previous_command = Some(first);
+            continue;
+        }
+
+        let number = format_number(token);
+        if let Some(has_dot) = last_number {
+            // A minus sign, or a second decimal point, already separates two numbers
+            let self_separated = number.starts_with('-') || (has_dot && number.starts_with('.'));
+            if !self_separated {
+                output.push(' ');
+            }
+        }
+        last_number = Some(number.contains('.'));
+        output.push_str(&number);
+    }
+
+    output
+}
+
+/// Remove every element for which `should_remove(name, attributes, content)` is true,
+/// with its content
+fn remove_elements<F>(svg: &str, should_remove: F) -> String
+where
+    F: Fn(&str, &[(String, String)], &str) -> bool,
+{
+    let tag_re = Regex::new(r"<(/?)([A-Za-z][\w:.-]*)([^>]*?)(/?)>").unwrap();
+    let mut output = String::new();
+    let mut copied_up_to = 0;
+
+    for cap in tag_re.captures_iter(svg) {
+        let whole = cap.get(0).unwrap();
+        // Inside an element already removed
+        if whole.start() < copied_up_to || !cap[1].is_empty() {
+            continue;
+        }
+        let name = &cap[2];
+
+        // An unclosed element runs to the end of the document
+        let (content, end) = if cap[4].is_empty() {
+            match find_closing_tag(svg, name, whole.end()) {
+                Some((content_end, end)) => (&svg[whole.end()..content_end], end),
+                None => (&svg[whole.end()..], svg.len()),
+            }
+        } else {
+            ("", whole.end())
+        };
+
+        if !should_remove(name, &parse_attributes(&cap[3]), content) {
+            continue;
+        }
+
+        output.push_str(&svg[copied_up_to..whole.start()]);
+        copied_up_to = end;
+    }
+
+    output.push_str(&svg[copied_up_to..]);
+    output
+}
+
+/// Start and end of the tag closing the `name` element whose content starts at `from`,
+/// skipping nested elements of the same name
+fn find_closing_tag(svg: &str, name: &str, from: usize) -> Option<(usize, usize)> {
+    let tag_re = Regex::new(&format!(r"<(/?){}(?:\s[^>]*?)?(/?)>", regex::escape(name))).unwrap();
+    let mut depth = 1;
+
+    for cap in tag_re.captures_iter(&svg[from..]) {
+        if !cap[2].is_empty() {
+            continue;
+        }
+        if cap[1].is_empty() {
+            depth += 1;
+        } else {
+            depth -= 1;
+            if depth == 0 {
+                let whole = cap.get(0).unwrap();
+                return Some((from + whole.start(), from + whole.end()));
+            }
+        }
+    }
+
+    None
+}
+
+/// Optimize the SVG files referenced by the page and its inline `<svg>` elements.
+/// With `write`, local SVG files and the local HTML file are updated, otherwise
+/// the savings are only reported.
+pub async fn optimize_svgs(html: &str, document: &Html, base_url: &str, write: bool) {
+    use colored::*;
+
+    println!("\n{}", "✏️  Optimizing SVG images...".cyan().bold());
+
+    let mut total_original = 0;
+    let mut total_optimized = 0;
+
+    let svg_selector = Selector::parse("img[src], object[data], embed[src]").unwrap();
+    let mut seen: HashSet<String> = HashSet::new();
+
+    for element in document.select(&svg_selector) {
+        let Some(src) = element.value().attr("src").or(element.value().attr("data")) else { continue };
+        if !src.split(['?', '#']).next().unwrap_or("").to_lowercase().ends_with(".svg") {
+            continue;
+        }
+
+        let svg_url = resolve_url(base_url, src);
+        if !seen.insert(svg_url.clone()) {
+            continue;
+        }
+
+        let svg = match fetch_resource(&svg_url).await.map(String::from_utf8) {
+            Ok(Ok(svg)) => svg,
+            _ => {
+                println!("  ⚠️  Failed to read {}", svg_url);
+                continue;
+            }
+        };
+
+        let optimized = optimize_svg(&svg);
+        total_original += svg.len();
+        total_optimized += optimized.len();
+
+        let should_write = write && !is_remote_url(&svg_url) && optimized.len() < svg.len();
+        if should_write && let Err(e) = fs::write(&svg_url, &optimized) {
+            println!("  ❌ Failed to write {}: {}", svg_url, e);
+            continue;
+        }
+        print_savings(&format!("Optimized {}", svg_url), svg.len(), optimized.len());
+    }
+
+    // Inline SVGs are replaced in the source text, so the rest of the markup is untouched.
+    // A nested <svg> is optimized with the outermost one.
+    let svg_tag_re = Regex::new(r"<svg\b[^>]*>").unwrap();
+    let mut optimized_html = String::new();
+    let mut copied_up_to = 0;
+    let mut inline_count = 0;
+
+    for start_tag in svg_tag_re.find_iter(html) {
+        if start_tag.start() < copied_up_to {
+            continue;
+        }
+        let end = if start_tag.as_str().ends_with("/>") {
+            start_tag.end()
+        } else {
+            match find_closing_tag(html, "svg", start_tag.end()) {
+                Some((_, end)) => end,
+                None => continue,
+            }
+        };
+
+        let svg = &html[start_tag.start()..end];
+        let optimized = optimize_svg(svg);
+        inline_count += 1;
+        total_original += svg.len();
+        total_optimized += optimized.len();
+        print_savings(&format!("Optimized inline <svg> #{}", inline_count), svg.len(), optimized.len());
+
+        optimized_html.push_str(&html[copied_up_to..start_tag.start()]);
+        optimized_html.push_str(&optimized);
+        copied_up_to = end;
+    }
+    optimized_html.push_str(&html[copied_up_to..]);
+
+    if write && inline_count > 0 && !is_remote_url(base_url) {
+        fs::write(base_url, &optimized_html)
+            .unwrap_or_else(|_| panic!("Failed to write to file: {}", base_url));
+        println!("  ✅ Updated {} inline SVGs in {}", inline_count, base_url);
+    }
+
+    let saved = total_original.saturating_sub(total_optimized);
+    let saved_percent = if total_original > 0 {
+        (saved as f64 / total_original as f64) * 100.0
+    } else {
+        0.0
+    };
+    println!("   Total SVG: {} bytes -> {} bytes", total_original, total_optimized);
+    println!("   Saved: {} bytes ({:.1}%)", saved.to_string().green().bold(), saved_percent);
+}
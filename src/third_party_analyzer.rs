@@ -0,0 +1,192 @@
+use publicsuffix::{List, Psl};
+use scraper::{Html, Selector};
+use std::collections::HashSet;
+use std::sync::LazyLock;
+use crate::css_analyzer::{Stylesheet, extract_css_references};
+use crate::resource_extractor::FetchedResources;
+use crate::url_resolver::{is_remote_url, resolve_url};
+
+/// Copy of https://publicsuffix.org/list/public_suffix_list.dat, telling where registrable domains start
+static PUBLIC_SUFFIXES: LazyLock<List> = LazyLock::new(|| {
+    include_str!("../data/public_suffix_list.dat").parse().expect("Invalid public suffix list")
+});
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum ThirdPartyCategory {
+    Analytics,
+    Advertising,
+    Social,
+    TagManager,
+    Cdn,
+    Video,
+    Other,
+}
+
+impl ThirdPartyCategory {
+    pub fn label(&self) -> &'static str {
+        match self {
+            ThirdPartyCategory::Analytics => "analytics",
+            ThirdPartyCategory::Advertising => "ads",
+            ThirdPartyCategory::Social => "social",
+            ThirdPartyCategory::TagManager => "tag manager",
+            ThirdPartyCategory::Cdn => "CDN",
+            ThirdPartyCategory::Video => "video",
+            ThirdPartyCategory::Other => "other",
+        }
+    }
+}
+
+/// A company or service and the domains it serves resources from
+struct KnownEntity {
+    name: &'static str,
+    category: ThirdPartyCategory,
+    domains: &'static [&'static str],
+}
+
+const KNOWN_ENTITIES: [KnownEntity; 22] = [
+    KnownEntity { name: "Google Analytics", category: ThirdPartyCategory::Analytics, domains: &["google-analytics.com", "analytics.google.com"] },
+    KnownEntity { name: "Hotjar", category: ThirdPartyCategory::Analytics, domains: &["hotjar.com", "hotjar.io"] },
+    KnownEntity { name: "Matomo", category: ThirdPartyCategory::Analytics, domains: &["matomo.cloud", "innocraft.cloud"] },
+    KnownEntity { name: "Segment", category: ThirdPartyCategory::Analytics, domains: &["segment.com", "segment.io"] },
+    KnownEntity { name: "Mixpanel", category: ThirdPartyCategory::Analytics, domains: &["mixpanel.com", "mxpnl.com"] },
+    KnownEntity { name: "Microsoft Clarity", category: ThirdPartyCategory::Analytics, domains: &["clarity.ms"] },
+    KnownEntity { name: "Google Tag Manager", category: ThirdPartyCategory::TagManager, domains: &["googletagmanager.com"] },
+    KnownEntity { name: "Adobe Experience Platform", category: ThirdPartyCategory::TagManager, domains: &["adobedtm.com", "omtrdc.net", "demdex.net"] },
+    KnownEntity { name: "Tealium", category: ThirdPartyCategory::TagManager, domains: &["tiqcdn.com", "tealiumiq.com"] },
+    KnownEntity { name: "Commanders Act", category: ThirdPartyCategory::TagManager, domains: &["tagcommander.com", "commander1.com"] },
+    KnownEntity { name: "Google Ads", category: ThirdPartyCategory::Advertising, domains: &["doubleclick.net", "googlesyndication.com", "googleadservices.com", "adservice.google.com"] },
+    KnownEntity { name: "Amazon Ads", category: ThirdPartyCategory::Advertising, domains: &["amazon-adsystem.com"] },
+    KnownEntity { name: "Criteo", category: ThirdPartyCategory::Advertising, domains: &["criteo.com", "criteo.net"] },
+    KnownEntity { name: "Taboola", category: ThirdPartyCategory::Advertising, domains: &["taboola.com"] },
+    KnownEntity { name: "Outbrain", category: ThirdPartyCategory::Advertising, domains: &["outbrain.com"] },
+    KnownEntity { name: "Facebook", category: ThirdPartyCategory::Social, domains: &["facebook.net", "facebook.com", "fbcdn.net"] },
+    KnownEntity { name: "Twitter", category: ThirdPartyCategory::Social, domains: &["twitter.com", "twimg.com", "x.com"] },
+    KnownEntity { name: "LinkedIn", category: ThirdPartyCategory::Social, domains: &["linkedin.com", "licdn.com"] },
+    KnownEntity { name: "YouTube", category: ThirdPartyCategory::Video, domains: &["youtube.com", "youtube-nocookie.com", "ytimg.com"] },
+    KnownEntity { name: "Vimeo", category: ThirdPartyCategory::Video, domains: &["vimeo.com", "vimeocdn.com"] },
+    KnownEntity { name: "Google Fonts", category: ThirdPartyCategory::Cdn, domains: &["fonts.googleapis.com", "fonts.gstatic.com"] },
+    KnownEntity { name: "Public CDNs", category: ThirdPartyCategory::Cdn, domains: &["cdnjs.cloudflare.com", "cdn.jsdelivr.net", "unpkg.com", "code.jquery.com", "ajax.googleapis.com", "bootstrapcdn.com"] },
+];
+
+/// Requests and bytes of the resources of one third party
+pub struct EntityUsage {
+    pub name: String,
+    pub category: ThirdPartyCategory,
+    pub requests: usize,
+    pub bytes: usize,
+    pub hosts: Vec<String>,
+}
+
+pub struct ThirdPartyAnalysis {
+    pub first_party_requests: usize,
+    pub first_party_bytes: usize,
+    /// Heaviest third parties first
+    pub entities: Vec<EntityUsage>,
+}
+
+/// The registrable part of a host name, `www.example.co.uk` -> `example.co.uk`
+pub fn site_of(host: &str) -> String {
+    let host = host.trim_end_matches('.');
+    if host.parse::<std::net::IpAddr>().is_ok() {
+        return host.to_string();
+    }
+    PUBLIC_SUFFIXES.domain(host.as_bytes())
+        .and_then(|domain| std::str::from_utf8(domain.as_bytes()).ok().map(str::to_string))
+        .unwrap_or_else(|| host.to_string())
+}
+
+fn host_of(url: &str) -> Option<String> {
+    url::Url::parse(url).ok().and_then(|u| u.host_str().map(|host| host.to_lowercase()))
+}
+
+/// Known entity a host belongs to, matching the domain and its subdomains
+fn find_entity(host: &str) -> Option<&'static KnownEntity> {
+    KNOWN_ENTITIES.iter().find(|entity| {
+        entity.domains.iter().any(|domain| host == *domain || host.ends_with(&format!(".{}", domain)))
+    })
+}
+
+/// Every resource loaded by the page markup or its stylesheets, each once
+fn collect_resource_urls(document: &Html, base_url: &str, stylesheets: &[Stylesheet]) -> Vec<String> {
+    let resource_selector = Selector::parse(
+        "link[href]:not([rel~='canonical']):not([rel~='alternate']):not([rel~='dns-prefetch']):not([rel~='preconnect']), \
+         script[src], img[src], iframe[src], source[src], video[src], audio[src], embed[src], object[data]"
+    ).unwrap();
+    let mut urls: Vec<String> = Vec::new();
+    let mut seen: HashSet<String> = HashSet::new();
+
+    for element in document.select(&resource_selector) {
+        let element = element.value();
+        let Some(href) = element.attr("href").or(element.attr("src")).or(element.attr("data")) else { continue };
+        if href.starts_with("data:") || href.starts_with('#') {
+            continue;
+        }
+        let resource_url = resolve_url(base_url, href);
+        if seen.insert(resource_url.clone()) {
+            urls.push(resource_url);
+        }
+    }
+
+    for stylesheet in stylesheets {
+        for reference in extract_css_references(&stylesheet.content, &stylesheet.url) {
+            if seen.insert(reference.url.clone()) {
+                urls.push(reference.url);
+            }
+        }
+    }
+
+    urls
+}
+
+/// Split the resources of the page between first and third parties, grouping the third
+/// parties by known entity, or by site when the entity is unknown. Resources are read from
+/// `fetched` when the page requests were already counted.
+pub async fn analyze_third_parties(document: &Html, base_url: &str, stylesheets: &[Stylesheet], fetched: &FetchedResources) -> ThirdPartyAnalysis {
+    let page_site = if is_remote_url(base_url) {
+        host_of(base_url).map(|host| site_of(&host))
+    } else {
+        None
+    };
+
+    let mut first_party_requests = 0;
+    let mut first_party_bytes = 0;
+    let mut entities: Vec<EntityUsage> = Vec::new();
+
+    for resource_url in collect_resource_urls(document, base_url, stylesheets) {
+        let bytes = fetched.get(&resource_url).await.map(|content| content.len()).unwrap_or(0);
+
+        // Local files and remote resources of the audited site are first party
+        let host = if is_remote_url(&resource_url) { host_of(&resource_url) } else { None };
+        let Some(host) = host.filter(|host| page_site.as_deref() != Some(site_of(host).as_str())) else {
+            first_party_requests += 1;
+            first_party_bytes += bytes;
+            continue;
+        };
+
+        let (name, category) = match find_entity(&host) {
+            Some(entity) => (entity.name.to_string(), entity.category),
+            None => (site_of(&host), ThirdPartyCategory::Other),
+        };
+
+        let usage = match entities.iter().position(|usage| usage.name == name) {
+            Some(index) => &mut entities[index],
+            None => {
+                entities.push(EntityUsage { name, category, requests: 0, bytes: 0, hosts: Vec::new() });
+                entities.last_mut().unwrap()
+            },
+        };
+        usage.requests += 1;
+        usage.bytes += bytes;
+        if !usage.hosts.contains(&host) {
+            usage.hosts.push(host);
+        }
+    }
+
+    entities.sort_by_key(|usage| std::cmp::Reverse(usage.bytes));
+
+    ThirdPartyAnalysis {
+        first_party_requests,
+        first_party_bytes,
+        entities,
+    }
+}
@@ -1,4 +1,4 @@
-use std::path::Path;
+use std::path::{Component, Path, PathBuf};
 
 pub fn resolve_url(base: &str, relative: &str) -> String {
     // Check if it's already an absolute URL
@@ -60,4 +60,50 @@ fn resolve_local_path(base: &str, relative: &str) -> String {
 /// Check if a resource path is local or remote
 pub fn is_remote_url(path: &str) -> bool {
     path.starts_with("http://") || path.starts_with("https://") || path.starts_with("//")
-}
\ No newline at end of file
+}
+
+/// Express `target` relative to the document at `base`, both being resolved URLs or paths
+pub fn relative_url(base: &str, target: &str) -> String {
+    if is_remote_url(base) || is_remote_url(target) {
+        return url::Url::parse(base).ok()
+            .zip(url::Url::parse(target).ok())
+            .and_then(|(base, target)| base.make_relative(&target))
+            .unwrap_or_else(|| target.to_string());
+    }
+
+    let base_path = Path::new(base.strip_prefix("file://").unwrap_or(base));
+    let base_dir = if base_path.is_dir() { base_path } else { base_path.parent().unwrap_or(Path::new(".")) };
+    // Paths relative to the working directory and canonical paths must be compared alike
+    let base_dir = absolute_path(base_dir);
+    let target = absolute_path(Path::new(target));
+    let base_components: Vec<_> = base_dir.components().collect();
+    let target_components: Vec<_> = target.components().collect();
+
+    let common = base_components.iter()
+        .zip(&target_components)
+        .take_while(|(a, b)| a == b)
+        .count();
+
+    let mut parts: Vec<String> = vec!["..".to_string(); base_components.len() - common];
+    parts.extend(target_components[common..].iter().map(|c| c.as_os_str().to_string_lossy().to_string()));
+    parts.join("/")
+}
+
+/// Canonical form of a local path, or for a missing file its absolute form with `.` and `..` resolved
+fn absolute_path(path: &Path) -> PathBuf {
+    if let Ok(canonical) = path.canonicalize() {
+        return canonical;
+    }
+    let absolute = std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf());
+    let mut normalized = PathBuf::new();
+    for component in absolute.components() {
+        match component {
+            Component::CurDir => {},
+            Component::ParentDir => {
+                normalized.pop();
+            },
+            other => normalized.push(other),
+        }
+    }
+    normalized
+}
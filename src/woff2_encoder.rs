@@ -0,0 +1,108 @@
+use std::io::Write;
+
+/// Tags with a one-byte code in the WOFF2 table directory, in code order
+const KNOWN_TAGS: [&[u8; 4]; 63] = [
+    b"cmap", b"head", b"hhea", b"hmtx", b"maxp", b"name", b"OS/2", b"post", b"cvt ", b"fpgm",
+    b"glyf", b"loca", b"prep", b"CFF ", b"VORG", b"EBDT", b"EBLC", b"gasp", b"hdmx", b"kern",
+    b"LTSH", b"PCLT", b"VDMX", b"vhea", b"vmtx", b"BASE", b"GDEF", b"GPOS", b"GSUB", b"EBSC",
+    b"JSTF", b"MATH", b"CBDT", b"CBLC", b"COLR", b"CPAL", b"SVG ", b"sbix", b"acnt", b"avar",
+    b"bdat", b"bloc", b"bsln", b"cvar", b"fdsc", b"feat", b"fmtx", b"fvar", b"gvar", b"hsty",
+    b"just", b"lcar", b"mort", b"morx", b"opbd", b"prop", b"trak", b"Zapf", b"Silf", b"Glat",
+    b"Gloc", b"Feat", b"Sill",
+];
+
+/// Code telling the table tag follows the flags byte
+const ARBITRARY_TAG: u8 = 63;
+/// Transform version meaning "not transformed" for glyf/loca (it is 0 for every other table)
+const GLYF_LOCA_NULL_TRANSFORM: u8 = 3 << 6;
+
+struct SfntTable<'a> {
+    tag: [u8; 4],
+    data: &'a [u8],
+}
+
+/// Wrap a TrueType/OpenType font in a WOFF2 container.
+/// Tables are stored untransformed, in a single Brotli stream.
+pub fn encode_woff2(sfnt: &[u8]) -> Result<Vec<u8>, String> {
+    let read_u16 = |offset: usize| -> Option<u16> {
+        Some(u16::from_be_bytes([*sfnt.get(offset)?, *sfnt.get(offset + 1)?]))
+    };
+    let read_u32 = |offset: usize| -> Option<u32> {
+        Some(u32::from_be_bytes(sfnt.get(offset..offset + 4)?.try_into().ok()?))
+    };
+
+    let flavor = read_u32(0).ok_or("font too short")?;
+    let table_count = read_u16(4).ok_or("font too short")? as usize;
+
+    let mut tables: Vec<SfntTable> = Vec::new();
+    for index in 0..table_count {
+        let record = 12 + index * 16;
+        let tag: [u8; 4] = sfnt.get(record..record + 4)
+            .and_then(|tag| tag.try_into().ok())
+            .ok_or("truncated table directory")?;
+        let offset = read_u32(record + 8).ok_or("truncated table directory")? as usize;
+        let length = read_u32(record + 12).ok_or("truncated table directory")? as usize;
+        let data = sfnt.get(offset..offset + length).ok_or("table outside of the font")?;
+        tables.push(SfntTable { tag, data });
+    }
+
+    let mut directory: Vec<u8> = Vec::new();
+    let mut uncompressed: Vec<u8> = Vec::new();
+    for table in &tables {
+        let known_index = KNOWN_TAGS.iter().position(|known| **known == table.tag);
+        let mut flags = known_index.map(|index| index as u8).unwrap_or(ARBITRARY_TAG);
+        if &table.tag == b"glyf" || &table.tag == b"loca" {
+            flags |= GLYF_LOCA_NULL_TRANSFORM;
+        }
+
+        directory.push(flags);
+        if known_index.is_none() {
+            directory.extend(table.tag);
+        }
+        write_uint_base128(&mut directory, table.data.len() as u32);
+        uncompressed.extend(table.data);
+    }
+
+    let mut compressed: Vec<u8> = Vec::new();
+    {
+        // Quality 11 with a 4MB window, as recommended for fonts
+        let mut compressor = brotli::CompressorWriter::new(&mut compressed, 4096, 11, 22);
+        compressor.write_all(&uncompressed).map_err(|e| format!("Brotli compression failed: {}", e))?;
+    }
+
+    // Size of the font once decoded: header, table records and 4-byte aligned tables
+    let total_sfnt_size = 12 + 16 * tables.len()
+        + tables.iter().map(|table| table.data.len().div_ceil(4) * 4).sum::<usize>();
+
+    const HEADER_SIZE: usize = 48;
+    let length = (HEADER_SIZE + directory.len() + compressed.len()).div_ceil(4) * 4;
+
+    let mut woff2: Vec<u8> = Vec::with_capacity(length);
+    woff2.extend(b"wOF2");
+    woff2.extend(flavor.to_be_bytes());
+    woff2.extend((length as u32).to_be_bytes());
+    woff2.extend((tables.len() as u16).to_be_bytes());
+    woff2.extend(0u16.to_be_bytes());
+    woff2.extend((total_sfnt_size as u32).to_be_bytes());
+    woff2.extend((compressed.len() as u32).to_be_bytes());
+    // Font version 1.0, then no metadata nor private blocks
+    woff2.extend(1u16.to_be_bytes());
+    woff2.extend(0u16.to_be_bytes());
+    woff2.extend([0u8; 20]);
+    woff2.extend(directory);
+    woff2.extend(compressed);
+    woff2.resize(length, 0);
+
+    Ok(woff2)
+}
+
+/// Big-endian base 128 with a continuation bit, as used by the WOFF2 table directory
+fn write_uint_base128(output: &mut Vec<u8>, value: u32) {
+    let mut bytes: Vec<u8> = vec![(value & 0x7F) as u8];
+    let mut rest = value >> 7;
+    while rest > 0 {
+        bytes.push((rest & 0x7F) as u8 | 0x80);
+        rest >>= 7;
+    }
+    output.extend(bytes.iter().rev());
+}